@@ -1,5 +1,5 @@
 use clap::{Args, ArgGroup, Parser, Subcommand};
-use psidb_lib::database::{Database, entry::action::Action};
+use psidb_lib::database::{Database, template, import, fsck::Severity, entry::{action::Action, transform::Transform, tree::TreeFilter, metadata::{Metadata, parser}}};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -15,6 +15,10 @@ enum Commands {
     Init(Init),
     /// Add a number of data files to the database
     AddData(AddData),
+    /// Add the files in a folder as datasets, with metadata taken from their paths
+    Import(Import),
+    /// Add the datasets or transforms listed in a CSV or JSON manifest
+    ImportManifest(ImportManifest),
     /// Add a transform (a script that modifies data) to the database
     AddTransform(AddTransform),
     /// Connect multiple transforms/data sets together
@@ -24,7 +28,57 @@ enum Commands {
     /// Chain multiple datasets together, creating a new dataset
     Chain(Chain),
     /// Link multiple transforms together, creating a new transform
-    Link(Link)
+    Link(Link),
+    /// Show or change a setting of the database
+    Config(Config),
+    /// Manage the metadata templates that new entries must follow
+    Template(Template),
+    /// Manage the named roots that paths are stored relative to, so that a database works on several machines
+    Roots(Roots),
+    /// Show the datasets and transforms that are out of date
+    Status(Status),
+    /// Check that the files of datasets did not change since they were added
+    Verify(Verify),
+    /// Check the database for inconsistencies, e.g. after it was edited by hand
+    Fsck(Fsck),
+    /// Upgrade a database written by an older version of psidb
+    Migrate(Migrate),
+    /// Show the datasets that track a file, directly or inside one of their directories
+    Which(Which),
+    /// Mark a dataset or transform as deprecated, making everything made from it stale
+    Deprecate(Deprecate),
+    /// Recompute everything made from a changed dataset or transform
+    Update(Update),
+    /// Register and apply the transforms described in a pipeline file
+    RunPipeline(RunPipeline),
+    /// Import the entries of another database
+    Merge(Merge),
+    /// Show the history of changes to the database
+    Log(Log),
+    /// Set or remove metadata of a dataset, transform or connection
+    SetMd(SetMd),
+    /// Read the metadata of the sidecar files of a dataset again
+    RefreshMd(RefreshMd),
+    /// Remove a dataset, transform or connection from the database
+    Rm(Rm),
+    /// Move or rename a file or folder and update the datasets and transforms under it
+    Mv(Mv),
+    /// Find the missing files of datasets in other folders by their contents
+    Relocate(Relocate),
+    /// Copy the files of a dataset into a folder, from the object store
+    Checkout(Checkout),
+    /// Revert the most recent changes to the database
+    Undo(Undo),
+    /// List the datasets, transforms and connections
+    List(List),
+    /// Show everything about a dataset, transform or connection
+    Show(Show),
+    /// Show what a dataset or transform was made from
+    Lineage(Lineage),
+    /// Print the provenance graph in the Graphviz DOT format
+    Graph(Graph),
+    /// Show the entries added, modified and removed between two dates
+    Diff(Diff)
 }
 
 #[derive(Args)]
@@ -44,11 +98,106 @@ struct AddData {
     #[clap(long = "md")]
     meta_data: Option<String>,
 
+    /// A JSON, TOML or YAML file with metadata (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
+    /// Only track the files of directories that match this glob, relative to the directory (can be repeated)
+    #[clap(long)]
+    include: Vec<String>,
+
+    /// Do not track the files of directories that match this glob, relative to the directory (can be repeated)
+    #[clap(long)]
+    exclude: Vec<String>,
+
+    /// Move the files into the object store of the database (read-only, shared by datasets with the same files) and
+    /// leave symlinks to them in their place, defaults to the store setting
+    #[clap(long, conflicts_with = "no-store")]
+    store: bool,
+
+    /// Leave the files where they are, even if the store setting is on
+    #[clap(long)]
+    no_store: bool,
+
     /// The paths to the data that make up a dataset
     #[clap(value_parser)]
     data_paths: Vec<String>
 }
 
+#[derive(Args)]
+struct Import {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The folder to import the files of
+    #[clap(value_parser)]
+    dir: String,
+
+    /// Only import the files whose path (relative to the folder) matches this glob
+    #[clap(long, default_value = "**/*")]
+    glob: String,
+
+    /// A regex matched against the relative path of each file, whose named captures become metadata, e.g. `sample(?P<sample>\w+)_T(?P<temperature>\d+)K`
+    #[clap(long)]
+    md_regex: Option<String>,
+
+    /// Make one dataset per file, per folder (dir), or per value of a named capture of --md-regex
+    #[clap(long, default_value = "file")]
+    group_by: String,
+
+    /// Metadata added to every dataset
+    #[clap(long = "md")]
+    meta_data: Option<String>,
+
+    /// A JSON, TOML or YAML file with metadata added to every dataset (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
+    /// Only show what would be imported
+    #[clap(long)]
+    dry_run: bool
+}
+
+#[derive(Args)]
+struct ImportManifest {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// A CSV file with a header row or a JSON array of objects, with one row per file or script
+    #[clap(value_parser)]
+    manifest: String,
+
+    /// Whether the rows are datasets or transforms
+    #[clap(long, value_enum, default_value = "data")]
+    kind: import::ManifestKind,
+
+    /// The column with the paths of the files or scripts (relative to the folder of the manifest)
+    #[clap(long, default_value = "path")]
+    paths_col: String,
+
+    /// The column with the arguments of the scripts, as a shell-quoted string or a JSON list
+    #[clap(long)]
+    args_col: Option<String>,
+
+    /// Rows with the same value in this column make up a single dataset or transform
+    #[clap(long)]
+    group_by: Option<String>,
+
+    /// Metadata added to every entry
+    #[clap(long = "md")]
+    meta_data: Option<String>,
+
+    /// A JSON, TOML or YAML file with metadata added to every entry (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
+    /// Only show what would be imported
+    #[clap(long)]
+    dry_run: bool
+}
+
 #[derive(Args)]
 struct AddTransform {
     /// Path to the database folder, defaults to $HOME/.psidb/
@@ -59,13 +208,21 @@ struct AddTransform {
     #[clap(long = "md")]
     meta_data: Option<String>,
 
+    /// A JSON, TOML or YAML file with metadata (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
     /// The paths to the scripts that make up a transform
     #[clap(value_parser)]
     script_paths: Vec<String>,
 
-    /// The arguments passed to each script, one string per script
-    #[clap(long = "args")]
-    script_args: Option<String>,
+    /// The arguments passed to each script as a shell-quoted string, give `--args` once per script (in order)
+    #[clap(long = "args", allow_hyphen_values = true)]
+    script_args: Vec<String>,
+
+    /// A single argument passed verbatim to a script, written as `<script index>=<argument>` (can be repeated)
+    #[clap(long = "arg", allow_hyphen_values = true)]
+    script_arg: Vec<String>,
 
     /// The commits of the versions of the scripts if they are tracked by git, one string per script
     #[clap(long = "hashes")]
@@ -88,7 +245,12 @@ struct Connect {
     #[clap(long = "md")]
     meta_data: Option<String>,
 
-    /// The action of the connection, one of apply, chain, or link
+    /// A JSON, TOML or YAML file with metadata (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
+    /// The action of the connection, one of apply, chain, link, or update (the outputs are newer versions of the
+    /// inputs, which `psidb update` records itself)
     #[clap(arg_enum, short, long)]
     action: Action,
 
@@ -119,13 +281,25 @@ struct Apply {
     #[clap(long = "md")]
     meta_data: Option<String>,
 
+    /// A JSON, TOML or YAML file with metadata (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
     /// The id of the transform to apply
     #[clap(short, long)]
     transform_id: u64,
 
     /// The datasets to apply the transform to
     #[clap(short, long)]
-    data_ids: Vec<u64>
+    data_ids: Vec<u64>,
+
+    /// Run the scripts from inside the output directory of the run
+    #[clap(long)]
+    cwd_out_dir: bool,
+
+    /// Run the scripts even if the transform was already applied to the same data
+    #[clap(long)]
+    force: bool
 }
 
 #[derive(Args)]
@@ -138,6 +312,10 @@ struct Chain {
     #[clap(long = "md")]
     meta_data: Option<String>,
 
+    /// A JSON, TOML or YAML file with metadata (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
     /// The ids of the transforms to chain together
     #[clap(short, long)]
     transform_ids: Vec<u64>
@@ -153,11 +331,525 @@ struct Link {
     #[clap(long = "md")]
     meta_data: Option<String>,
 
+    /// A JSON, TOML or YAML file with metadata (the entries of --md override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
     /// The ids of the datasets to link together
     #[clap(short, long)]
     data_ids: Vec<u64>
 }
 
+#[derive(Args)]
+struct Config {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The setting to show or change (data_root, data_template, transform_template, sidecars, sidecar_prefix,
+    /// extractors or store). sidecars is a comma-separated list of patterns of metadata files next to data files, where {}
+    /// stands for the file name and {stem} for the file name without its extension, e.g. `{}.json,{stem}.meta.yaml`.
+    /// extractors is a comma-separated list of the metadata extractors that run on new data (stat, mime or table).
+    /// store (true or false) says whether new data (from `add-data`, `import`, `import-manifest`, `apply`, `update` and
+    /// `run-pipeline`) has its files moved into the object store by default.
+    #[clap(value_parser)]
+    key: String,
+
+    /// The new value of the setting
+    #[clap(value_parser)]
+    value: Option<String>,
+
+    /// Reset the setting to its default value
+    #[clap(long, conflicts_with = "value")]
+    unset: bool
+}
+
+#[derive(Args)]
+struct Template {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    #[clap(subcommand)]
+    command: TemplateCommands
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// Add a template from a RON file, or replace the template with the same name
+    Add {
+        /// The template file, e.g. `(name: "sample", fields: [(key: "temperature", type: Some("float"), required: true)])`
+        #[clap(value_parser)]
+        path: String
+    },
+    /// List the templates
+    List,
+    /// Show the fields of a template
+    Show {
+        /// The name of the template
+        #[clap(value_parser)]
+        name: String
+    },
+    /// Remove a template
+    Rm {
+        /// The name of the template
+        #[clap(value_parser)]
+        name: String
+    }
+}
+
+#[derive(Args)]
+struct Roots {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    #[clap(subcommand)]
+    command: RootsCommands
+}
+
+#[derive(Subcommand)]
+enum RootsCommands {
+    /// List the roots and where they are on this machine
+    List,
+    /// Add a root (or move it). Paths under it are stored as `$name/...`, and can be given that way.
+    Add {
+        /// The name of the root
+        #[clap(value_parser)]
+        name: String,
+
+        /// Where the root is
+        #[clap(value_parser)]
+        path: String,
+
+        /// Only set where the root is on this machine (in ~/.config/psidb/config.toml)
+        #[clap(long)]
+        local: bool
+    },
+    /// Remove a root
+    Rm {
+        /// The name of the root
+        #[clap(value_parser)]
+        name: String,
+
+        /// Only remove where the root is on this machine
+        #[clap(long)]
+        local: bool
+    },
+    /// Replace the prefix old by new in the paths of every dataset and transform
+    Remap {
+        /// The old prefix, e.g. /scratch/alice
+        #[clap(value_parser)]
+        old: String,
+
+        /// The new prefix, e.g. /mnt/cluster/alice
+        #[clap(value_parser)]
+        new: String
+    }
+}
+
+#[derive(Args)]
+struct Status {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>
+}
+
+#[derive(Args)]
+struct Verify {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The ids of the datasets to check, defaults to every dataset
+    #[clap(value_parser)]
+    data_ids: Vec<u64>
+}
+
+#[derive(Args)]
+struct Fsck {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// Fix the issues that can be fixed mechanically, e.g. references to entries that do not exist
+    #[clap(long)]
+    repair: bool
+}
+
+#[derive(Args)]
+struct Migrate {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// Only list the upgrades the database needs, and fail if it needs any
+    #[clap(long)]
+    check: bool
+}
+
+#[derive(Args)]
+struct Which {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The path of the file
+    #[clap(value_parser)]
+    path: String
+}
+
+#[derive(Args)]
+struct Deprecate {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the dataset or transform to deprecate
+    #[clap(value_parser)]
+    id: u64,
+
+    /// Why the entry is deprecated
+    #[clap(long)]
+    reason: Option<String>
+}
+
+#[derive(Args)]
+struct Update {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the dataset or transform that changed
+    #[clap(value_parser)]
+    id: u64,
+
+    /// Only print the transforms that would be applied
+    #[clap(long)]
+    dry_run: bool,
+
+    /// The number of independent transforms to run at once
+    #[clap(short, long, default_value_t = 1)]
+    jobs: usize
+}
+
+#[derive(Args)]
+struct RunPipeline {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The pipeline file (e.g. psidb.pipeline.ron)
+    #[clap(value_parser)]
+    pipeline_path: String
+}
+
+#[derive(Args)]
+struct Merge {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The database to import: its db.ron file, its .psidb folder, or the folder that contains it
+    #[clap(value_parser)]
+    other: String
+}
+
+#[derive(Args)]
+struct Log {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// Only show the changes to the entry with this id
+    #[clap(long)]
+    id: Option<u64>,
+
+    /// Only show the changes made since this date (e.g. 2024-03-01 or 2024-03-01T12:00:00Z)
+    #[clap(long)]
+    since: Option<String>
+}
+
+#[derive(Args)]
+struct RefreshMd {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the dataset
+    #[clap(value_parser)]
+    id: u64
+}
+
+#[derive(Args)]
+struct Mv {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The file or folder to move
+    #[clap(value_parser)]
+    src: String,
+
+    /// Where to move it (into it if it is a folder)
+    #[clap(value_parser)]
+    dst: String,
+
+    /// Only update the database, for files that were already moved by another tool
+    #[clap(long)]
+    db_only: bool
+}
+
+#[derive(Args)]
+struct Relocate {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// A folder to look for the missing files in (can be repeated)
+    #[clap(long, required = true)]
+    search: Vec<String>,
+
+    /// Apply the relocations without asking
+    #[clap(short, long)]
+    yes: bool
+}
+
+#[derive(Args)]
+struct Checkout {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the dataset
+    #[clap(value_parser)]
+    id: u64,
+
+    /// The folder to copy the files into (created if needed)
+    #[clap(value_parser)]
+    dir: String
+}
+
+#[derive(Args)]
+struct SetMd {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the entry
+    #[clap(value_parser)]
+    id: u64,
+
+    /// The metadata to set
+    #[clap(value_parser)]
+    meta_data: Option<String>,
+
+    /// A JSON, TOML or YAML file with metadata to set (the entries of meta_data override its keys)
+    #[clap(long)]
+    md_file: Option<String>,
+
+    /// A metadata key to remove (can be repeated)
+    #[clap(long)]
+    unset: Vec<String>
+}
+
+#[derive(Args)]
+struct Rm {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the entry to remove
+    #[clap(value_parser)]
+    id: u64
+}
+
+#[derive(Args)]
+struct Undo {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The number of operations to revert
+    #[clap(value_parser, default_value_t = 1)]
+    n: usize
+}
+
+#[derive(Args)]
+struct List {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// Only list the entries whose metadata matches this condition, e.g. `temperature>=300` or `sample` (can be repeated)
+    #[clap(long = "where")]
+    conditions: Vec<String>,
+
+    /// Sort the entries by the value of this metadata key (`-key` for descending order)
+    #[clap(long, allow_hyphen_values = true)]
+    sort: Option<String>,
+
+    /// Show the database as it was at this date (e.g. 2024-03-01 or 2024-03-01T12:00:00Z)
+    #[clap(long)]
+    as_of: Option<String>
+}
+
+#[derive(Args)]
+struct Show {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the entry
+    #[clap(value_parser)]
+    id: u64,
+
+    /// Show the entry as it was at this date (e.g. 2024-03-01 or 2024-03-01T12:00:00Z)
+    #[clap(long)]
+    as_of: Option<String>
+}
+
+#[derive(Args)]
+struct Lineage {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The id of the dataset or transform
+    #[clap(value_parser)]
+    id: u64,
+
+    /// Show the lineage as it was at this date (e.g. 2024-03-01 or 2024-03-01T12:00:00Z)
+    #[clap(long)]
+    as_of: Option<String>
+}
+
+#[derive(Args)]
+struct Graph {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// Show the graph as it was at this date (e.g. 2024-03-01 or 2024-03-01T12:00:00Z)
+    #[clap(long)]
+    as_of: Option<String>
+}
+
+#[derive(Args)]
+struct Diff {
+    /// Path to the database folder, defaults to $HOME/.psidb/
+    #[clap(long = "db")]
+    db_path: Option<String>,
+
+    /// The earlier date (e.g. 2024-03-01 or 2024-03-01T12:00:00Z)
+    #[clap(long)]
+    from: String,
+
+    /// The later date, defaults to now
+    #[clap(long)]
+    to: Option<String>
+}
+
+fn load_as_of(db_path: Option<&str>, as_of: Option<&str>) -> Result<Database, Box<dyn std::error::Error>> {
+    match as_of {
+        Some(time) => Database::at(db_path, time),
+        None => Database::load(db_path)
+    }
+}
+
+fn print_import_report(report: &import::ImportReport, kind: &str) {
+    for entry in &report.added {
+        let mut md: Vec<String> = entry.md.iter().filter(|(k, _)| *k != "time").map(|(k, v)| parser::format_entry(k, v).unwrap_or_else(|| format!("{}={}", k, v))).collect();
+        md.sort();
+        let md = if md.is_empty() { String::new() } else { format!(" ({})", md.join(", ")) };
+        match entry.id {
+            Some(id) => println!("Added {} {}: {}{}", kind, id, entry.paths.join(", "), md),
+            None => println!("Would add {}: {}{}", kind, entry.paths.join(", "), md)
+        }
+    }
+    for (id, paths) in &report.existing {
+        println!("Already in the database as {} {}: {}", kind, id, paths.join(", "));
+    }
+}
+
+// The --md string, which includes the metadata of --md-file
+fn get_md_str(meta_data: Option<String>, md_file: Option<String>) -> Option<String> {
+    match md_file {
+        Some(md_file) => Some(format!("@{};{}", parser::quote(&md_file), meta_data.unwrap_or_default())),
+        None => meta_data
+    }
+}
+
+fn describe_entry(db: &Database, id: u64) -> String {
+    if let Some(data) = db.get_data(id) {
+        data.to_string()
+    } else if let Some(transform) = db.get_transform(id) {
+        transform.to_string()
+    } else if let Some(connection) = db.get_connection(id) {
+        connection.to_string()
+    } else {
+        format!("{} (missing)", id)
+    }
+}
+
+fn print_md(md: &Metadata) {
+    let mut keys: Vec<&String> = md.keys().collect();
+    keys.sort();
+    for key in keys {
+        println!("    md {} = {} ({})", key, md[key], md[key].type_name());
+    }
+}
+
+fn print_lineage(db: &Database, id: u64, depth: usize, seen: &mut std::collections::HashSet<u64>) {
+    let indent = "    ".repeat(depth);
+    if !seen.insert(id) {
+        println!("{}{} (see above)", indent, describe_entry(db, id));
+        return;
+    }
+    println!("{}{}", indent, describe_entry(db, id));
+    for connection in db.in_connections(id) {
+        println!("{}  <- {} (connection {})", indent, connection.action, connection.id);
+        for parent in connection.in_ids() {
+            print_lineage(db, parent, depth + 1, seen);
+        }
+    }
+}
+
+fn get_other_db_path(other: &str) -> std::path::PathBuf {
+    let path = std::path::Path::new(other);
+    if path.is_file() {
+        path.to_path_buf()
+    } else if path.join("db.ron").exists() {
+        path.join("db.ron")
+    } else {
+        path.join(".psidb").join("db.ron")
+    }
+}
+
+fn get_script_args(num_scripts: usize, script_args: &[String], script_arg: &[String]) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    if script_args.len() > num_scripts {
+        println!("Error: Got {} `--args` for {} scripts", script_args.len(), num_scripts);
+        return Err(format!("Got {} `--args` for {} scripts", script_args.len(), num_scripts).into());
+    }
+
+    // Split the `--args` strings into shell words and give the remaining scripts no arguments
+    let mut args = Transform::split_args(script_args)?;
+    args.resize(num_scripts, vec![]);
+
+    // Append the verbatim `--arg <script index>=<argument>` arguments
+    for arg in script_arg {
+        let idx = arg.split_once('=').and_then(|(idx, word)| Some((idx.parse::<usize>().ok()?, word)));
+        match idx {
+            Some((idx, word)) if idx < num_scripts => args[idx].push(word.to_owned()),
+            _ => {
+                println!("Error: `--arg {}` must be of the form <script index>=<argument> with a script index below {}", arg, num_scripts);
+                return Err(format!("`--arg {}` must be of the form <script index>=<argument> with a script index below {}", arg, num_scripts).into());
+            }
+        }
+    }
+    Ok(args)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Cli::parse();
 
@@ -165,42 +857,513 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Init(Init{db_path}) => {
             Database::init(db_path.as_deref())?;
         }
-        Commands::AddData(AddData{db_path, meta_data, data_paths}) => {
+        Commands::AddData(AddData{db_path, meta_data, md_file, include, exclude, store, no_store, data_paths}) => {
             let mut db = Database::load(db_path.as_deref())?;
-            let id = db.add_data(&data_paths, meta_data.as_deref())?;
+            let store = if store { Some(true) } else if no_store { Some(false) } else { None };
+            let id = db.add_filtered_data(&data_paths, get_md_str(meta_data, md_file).as_deref(), TreeFilter { include, exclude }, store)?;
             db.write()?;
             println!("Added data with id {}", id);
         }
-        Commands::AddTransform(AddTransform{db_path, meta_data, script_paths, script_args, script_git_hashes}) => {
+        Commands::Import(Import{db_path, dir, glob, md_regex, group_by, meta_data, md_file, dry_run}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let report = db.import(&dir, &glob, md_regex.as_deref(), &group_by.as_str().into(), get_md_str(meta_data, md_file).as_deref(), dry_run)?;
+            print_import_report(&report, "data");
+            for path in &report.unmatched {
+                println!("Does not match --md-regex: {}", path);
+            }
+            let num_files: usize = report.added.iter().map(|d| d.paths.len()).sum();
+            let verb = if dry_run { "Would import" } else { "Imported" };
+            println!("{} {} datasets ({} files), {} already in the database, {} files not matching --md-regex", verb, report.added.len(), num_files, report.existing.len(), report.unmatched.len());
+            if !dry_run {
+                db.write()?;
+            }
+        }
+        Commands::ImportManifest(ImportManifest{db_path, manifest, kind, paths_col, args_col, group_by, meta_data, md_file, dry_run}) => {
             let mut db = Database::load(db_path.as_deref())?;
-            let id = db.add_transform(&script_paths, script_args.as_deref(), script_git_hashes.as_deref(), meta_data.as_deref())?;
+            let columns = import::ManifestColumns { paths: &paths_col, args: args_col.as_deref(), group_by: group_by.as_deref() };
+            let report = db.import_manifest(&manifest, kind, &columns, get_md_str(meta_data, md_file).as_deref(), dry_run)?;
+            let kind = if kind == import::ManifestKind::Data { "data" } else { "transform" };
+            print_import_report(&report, kind);
+            let verb = if dry_run { "Would import" } else { "Imported" };
+            println!("{} {} entries, {} already in the database", verb, report.added.len(), report.existing.len());
+
+            // Everything is saved at once, so a manifest with an invalid row adds nothing
+            if !dry_run {
+                db.write()?;
+            }
+        }
+        Commands::AddTransform(AddTransform{db_path, meta_data, md_file, script_paths, script_args, script_arg, script_git_hashes}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let script_args = get_script_args(script_paths.len(), &script_args, &script_arg)?;
+            let id = db.add_transform(&script_paths, Some(&script_args), script_git_hashes.as_deref(), get_md_str(meta_data, md_file).as_deref())?;
             db.write()?;
             println!("Added transform with id {}", id);
         }
-        Commands::Connect(Connect{db_path, meta_data, action, in_data_ids, out_data_ids, in_transform_ids, out_transform_ids}) => {
+        Commands::Connect(Connect{db_path, meta_data, md_file, action, in_data_ids, out_data_ids, in_transform_ids, out_transform_ids}) => {
             let mut db = Database::load(db_path.as_deref())?;
-            let id = db.connect(action, in_data_ids.as_deref(), out_data_ids.as_deref(), in_transform_ids.as_deref(), out_transform_ids.as_deref(), meta_data.as_deref())?;
+            let id = db.connect(action, in_data_ids.as_deref(), out_data_ids.as_deref(), in_transform_ids.as_deref(), out_transform_ids.as_deref(), get_md_str(meta_data, md_file).as_deref())?;
             db.write()?;
             println!("Added a connection with id {}", id);
         }
-        Commands::Apply(Apply{db_path, meta_data, transform_id, data_ids}) => {
+        Commands::Apply(Apply{db_path, meta_data, md_file, transform_id, data_ids, cwd_out_dir, force}) => {
             let mut db = Database::load(db_path.as_deref())?;
-            let (data_id, connect_id) = db.apply(transform_id, &data_ids, meta_data.as_deref())?;
+            let (data_id, connect_id, cache_hit) = db.apply(transform_id, &data_ids, get_md_str(meta_data, md_file).as_deref(), cwd_out_dir, force)?;
+            if cache_hit {
+                println!("Reusing data with id {} from connection with id {} (use --force to run the transform again)", data_id, connect_id);
+                return Ok(());
+            }
             db.write()?;
             println!("Added data with id {} and connection with id {}", data_id, connect_id);
         }
-        Commands::Chain(Chain{db_path, meta_data, transform_ids}) => {
+        Commands::Chain(Chain{db_path, meta_data, md_file, transform_ids}) => {
             let mut db = Database::load(db_path.as_deref())?;
-            let (transform_id, connect_id) = db.chain(&transform_ids, meta_data.as_deref())?;
+            let (transform_id, connect_id) = db.chain(&transform_ids, get_md_str(meta_data, md_file).as_deref())?;
             db.write()?;
             println!("Added transform with id {} and connection with id {}", transform_id, connect_id);
         }
-        Commands::Link(Link{db_path, meta_data, data_ids}) => {
+        Commands::Link(Link{db_path, meta_data, md_file, data_ids}) => {
             let mut db = Database::load(db_path.as_deref())?;
-            let (data_id, connect_id) = db.link(&data_ids, meta_data.as_deref())?;
+            let (data_id, connect_id) = db.link(&data_ids, get_md_str(meta_data, md_file).as_deref())?;
             db.write()?;
             println!("Added data with id {} and connection with id {}", data_id, connect_id);
         }
+        Commands::Config(Config{db_path, key, value, unset}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            if value.is_none() && !unset {
+                println!("{} = {}", key, db.get_setting(&key)?.unwrap_or_else(|| "(default)".to_owned()));
+                return Ok(());
+            }
+            db.set_setting(&key, value.as_deref())?;
+            db.write()?;
+            println!("Set {} to {}", key, value.as_deref().unwrap_or("its default value"));
+        }
+        Commands::Template(Template{db_path, command}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            match command {
+                TemplateCommands::Add{path} => {
+                    let template = template::Template::load(&path)?;
+                    let name = template.name.clone();
+                    db.set_template(template)?;
+                    db.write()?;
+                    println!("Saved template {}", name);
+                }
+                TemplateCommands::List => {
+                    for template in db.get_templates() {
+                        let mut defaults = vec![];
+                        if db.get_setting("data_template")?.as_ref() == Some(&template.name) {
+                            defaults.push("data");
+                        }
+                        if db.get_setting("transform_template")?.as_ref() == Some(&template.name) {
+                            defaults.push("transforms");
+                        }
+                        if defaults.is_empty() {
+                            println!("{} ({} fields)", template.name, template.fields.len());
+                        } else {
+                            println!("{} ({} fields, default for {})", template.name, template.fields.len(), defaults.join(" and "));
+                        }
+                    }
+                }
+                TemplateCommands::Show{name} => {
+                    let Some(template) = db.get_template(&name) else {
+                        println!("Error: Template {} does not exist", name);
+                        return Err(format!("Template {} does not exist", name).into());
+                    };
+                    println!("template {}", template.name);
+                    for field in &template.fields {
+                        println!("    {}", field);
+                    }
+                }
+                TemplateCommands::Rm{name} => {
+                    db.remove_template(&name)?;
+                    db.write()?;
+                    println!("Removed template {}", name);
+                }
+            }
+        }
+        Commands::Roots(Roots{db_path, command}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            match command {
+                RootsCommands::List => {
+                    for root in db.get_roots() {
+                        match &root.local_path {
+                            Some(local_path) => println!("{} = {} (here {})", root.name, root.path, local_path),
+                            None => println!("{} = {}", root.name, root.path)
+                        }
+                    }
+                }
+                RootsCommands::Add{name, path, local} => {
+                    db.set_root(&name, &path, local)?;
+                    if local {
+                        println!("Root {} is at {} on this machine", name, db.resolve_path(&format!("${}", name)));
+                    } else {
+                        db.write()?;
+                        println!("Set root {} to {}", name, db.resolve_path(&format!("${}", name)));
+                    }
+                }
+                RootsCommands::Rm{name, local} => {
+                    db.remove_root(&name, local)?;
+                    if !local {
+                        db.write()?;
+                    }
+                    println!("Removed root {}", name);
+                }
+                RootsCommands::Remap{old, new} => {
+                    let changed = db.remap_paths(&old, &new)?;
+                    db.write()?;
+                    println!("Rewrote the paths of {} entries", changed.len());
+                    for id in changed {
+                        println!("    {}", describe_entry(&db, id));
+                    }
+                }
+            }
+        }
+        Commands::Status(Status{db_path}) => {
+            let db = Database::load(db_path.as_deref())?;
+            let stale = db.status();
+            if stale.is_empty() {
+                println!("Everything is up to date");
+            }
+            for s in stale {
+                let kind = if db.get_data(s.id).is_some() { "Data" } else { "Transform" };
+                println!("{} {}", kind, s);
+            }
+        }
+        Commands::Verify(Verify{db_path, data_ids}) => {
+            let db = Database::load(db_path.as_deref())?;
+            let data_ids = if data_ids.is_empty() { None } else { Some(data_ids.as_slice()) };
+            let changed = db.verify(data_ids)?;
+            if changed.is_empty() {
+                println!("All files match the database");
+            }
+            for data in changed {
+                for path in data.paths {
+                    let state = if std::path::Path::new(&path).exists() { "modified" } else { "missing" };
+                    println!("Data {}: {} ({})", data.data_id, path, state);
+                }
+                for (file, change) in data.files {
+                    println!("    {} ({})", file, change);
+                }
+                for path in data.sidecars {
+                    let state = if std::path::Path::new(&path).exists() { "modified" } else { "missing" };
+                    println!("Data {}: sidecar {} ({}, see `psidb refresh-md {}`)", data.data_id, path, state, data.data_id);
+                }
+            }
+        }
+        Commands::Fsck(Fsck{db_path, repair}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            if repair {
+                let fixed = db.repair();
+                for issue in &fixed {
+                    println!("fixed: {}", issue);
+                }
+                if !fixed.is_empty() {
+                    db.write()?;
+                }
+            }
+            let issues = db.check();
+            if issues.is_empty() {
+                println!("No issues found");
+                return Ok(());
+            }
+            for issue in &issues {
+                let hint = if issue.fixable() { " (fixable with --repair)" } else { "" };
+                println!("{}: {}{}", issue.severity(), issue, hint);
+            }
+            let errors = issues.iter().filter(|issue| issue.severity() == Severity::Error).count();
+            println!("{} errors, {} warnings", errors, issues.len() - errors);
+            if errors > 0 {
+                return Err(format!("The database has {} errors", errors).into());
+            }
+        }
+        Commands::Migrate(Migrate{db_path, check}) => {
+            let db_file = Database::get_psidb_dir(db_path.as_deref()).join("db.ron");
+            if !db_file.exists() {
+                println!("Error: {} does not exist", db_file.to_str().unwrap());
+                return Err(format!("{} does not exist", db_file.to_str().unwrap()).into());
+            }
+            let version = Database::read_format_version(&db_file)?;
+            let pending = Database::pending_migrations(&db_file)?;
+            if pending.is_empty() {
+                println!("The database is up to date (format version {})", version);
+                return Ok(());
+            }
+            if check {
+                println!("The database has format version {} and needs these upgrades:", version);
+                for (i, migration) in pending.iter().enumerate() {
+                    println!("    {}: {}", version as usize + i + 1, migration.description);
+                }
+                return Err(format!("The database needs to be upgraded to format version {}", version as usize + pending.len()).into());
+            }
+            // Loading the database upgrades it
+            Database::load(db_path.as_deref())?;
+        }
+        Commands::Which(Which{db_path, path}) => {
+            let db = Database::load(db_path.as_deref())?;
+            let found = db.which(&path)?;
+            if found.is_empty() {
+                println!("No dataset tracks {}", path);
+            }
+            for (id, data_path) in found {
+                println!("data {}: {}", id, data_path);
+            }
+        }
+        Commands::Update(Update{db_path, id, dry_run, jobs}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            if dry_run {
+                let steps = db.update_plan(id)?;
+                if steps.is_empty() {
+                    println!("Nothing to update");
+                }
+                for (i, step) in steps.iter().enumerate() {
+                    println!("{}. {}", i + 1, step);
+                }
+                return Ok(());
+            }
+
+            let report = db.update(id, jobs)?;
+            db.write()?;
+            for (old_id, new_id) in &report.new_transforms {
+                println!("Added transform with id {} as the new version of transform {}", new_id, old_id);
+            }
+            for (old_id, new_id) in &report.updated {
+                println!("Added data with id {} as the new version of data {}", new_id, old_id);
+            }
+            for (connect_id, error) in &report.failed {
+                println!("Failed to recompute connection {}: {}", connect_id, error);
+            }
+            for connect_id in &report.skipped {
+                println!("Skipped connection {} because a transform it needs failed", connect_id);
+            }
+            if !report.failed.is_empty() {
+                return Err(format!("{} transforms failed", report.failed.len()).into());
+            }
+        }
+        Commands::RunPipeline(RunPipeline{db_path, pipeline_path}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let results = db.run_pipeline(std::path::Path::new(&pipeline_path));
+
+            // Keep the steps that ran even if a later one failed
+            db.write()?;
+            for step in results? {
+                let how = if step.cache_hit { "Reused" } else { "Added" };
+                println!("{}: {} data with id {} and connection with id {}", step.name, how, step.data_id, step.connection_id);
+            }
+        }
+        Commands::Deprecate(Deprecate{db_path, id, reason}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            db.deprecate(id, reason.as_deref())?;
+            db.write()?;
+            println!("Deprecated entry {}", id);
+        }
+        Commands::Merge(Merge{db_path, other}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let other_db_path = get_other_db_path(&other);
+            if !other_db_path.exists() {
+                println!("Error: {} does not exist", other_db_path.to_str().unwrap());
+                return Err(format!("{} does not exist", other_db_path.to_str().unwrap()).into());
+            }
+            let report = db.merge(Database::read_from(&other_db_path)?)?;
+            db.write()?;
+
+            println!("Imported {} entries, {} were already in the database", report.added.len(), report.matched.len());
+            for (other_id, id) in &report.added {
+                println!("Added entry {} as id {}", other_id, id);
+            }
+            for id in &report.dangling {
+                println!("Skipped connection {}, which refers to entries that do not exist", id);
+            }
+            for conflict in &report.conflicts {
+                println!("Conflict: {}", conflict);
+            }
+        }
+        Commands::Log(Log{db_path, id, since}) => {
+            let db = Database::load(db_path.as_deref())?;
+            for event in db.log(id, since.as_deref())? {
+                println!("{}", event);
+                for change in &event.changes {
+                    println!("    {}", change);
+                }
+                for m in &event.moves {
+                    println!("    moved {} to {}", m.src, m.dst);
+                }
+            }
+        }
+        Commands::SetMd(SetMd{db_path, id, meta_data, md_file, unset}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            db.set_md(id, get_md_str(meta_data, md_file).as_deref(), &unset)?;
+            db.write()?;
+            println!("Updated the metadata of entry {}", id);
+        }
+        Commands::RefreshMd(RefreshMd{db_path, id}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let sidecars = db.refresh_md(id)?;
+            db.write()?;
+            println!("Read the metadata of data {} from {} sidecars", id, sidecars.len());
+            for sidecar in sidecars {
+                println!("    {}", sidecar);
+            }
+        }
+        Commands::Mv(Mv{db_path, src, dst, db_only}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let report = db.move_path(&src, &dst, db_only)?;
+            if let Err(e) = db.write() {
+                // Keep the files where the database says they are
+                if !db_only {
+                    std::fs::rename(&report.dst, &report.src)?;
+                }
+                return Err(e);
+            }
+            if !db_only {
+                println!("Moved {} to {}", report.src, report.dst);
+            }
+            println!("Updated {} entries", report.rewritten.len());
+            for id in &report.rewritten {
+                println!("    {}", describe_entry(&db, *id));
+            }
+            for (id, old_path, new_path) in &report.relocated {
+                println!("Data {}: found {} at {} by its contents", id, old_path, new_path);
+            }
+            for (id, path) in &report.missing {
+                println!("Data {}: {} is missing", id, path);
+            }
+        }
+        Commands::Relocate(Relocate{db_path, search, yes}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let found = db.find_relocations(&search)?;
+            println!("Scanned {} files ({} read, the others were in the hash cache)", found.scanned, found.hashed);
+            for (id, path) in &found.skipped {
+                println!("Data {}: {} is a missing folder, move it back or use `psidb mv --db-only`", id, path);
+            }
+            for (id, path) in &found.not_found {
+                println!("Data {}: {} was not found", id, path);
+            }
+            if found.found.is_empty() {
+                println!("Nothing to relocate");
+                return Ok(());
+            }
+            for relocation in &found.found {
+                let others = if relocation.candidates > 1 { format!(" ({} files have these contents)", relocation.candidates) } else { String::new() };
+                println!("Data {}: {} -> {}{}", relocation.data_id, relocation.old_path, relocation.new_path, others);
+            }
+            if !yes {
+                print!("Apply these {} relocations? [y/N] ", found.found.len());
+                std::io::Write::flush(&mut std::io::stdout())?;
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                    println!("Nothing was changed");
+                    return Ok(());
+                }
+            }
+            db.relocate(&found.found)?;
+            db.write()?;
+            println!("Relocated {} files", found.found.len());
+        }
+        Commands::Checkout(Checkout{db_path, id, dir}) => {
+            let db = Database::load(db_path.as_deref())?;
+            for path in db.checkout(id, &dir)? {
+                println!("Checked out {}", path);
+            }
+        }
+        Commands::Rm(Rm{db_path, id}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            db.remove(id)?;
+            db.write()?;
+            println!("Removed entry {}", id);
+        }
+        Commands::List(List{db_path, conditions, sort, as_of}) => {
+            let db = load_as_of(db_path.as_deref(), as_of.as_deref())?;
+            for id in db.query(&conditions, sort.as_deref())? {
+                println!("{}", describe_entry(&db, id));
+            }
+        }
+        Commands::Show(Show{db_path, id, as_of}) => {
+            let db = load_as_of(db_path.as_deref(), as_of.as_deref())?;
+            if let Some(data) = db.get_data(id) {
+                println!("{}", data);
+                println!("    uid {}", data.uid);
+                for (path, hash) in data.paths.iter().zip(&data.hashes) {
+                    println!("    sha256 {} {}", hash, path);
+                }
+                for tree in &data.trees {
+                    println!("    tree {} ({} files, {} bytes)", tree.root, tree.files.len(), tree.files.iter().map(|f| f.size).sum::<u64>());
+                }
+                if data.stored {
+                    println!("    stored in {}", db.get_objects_dir().to_str().unwrap());
+                }
+                if !data.filter.is_empty() {
+                    println!("    include [{}] exclude [{}]", data.filter.include.join(", "), data.filter.exclude.join(", "));
+                }
+                for sidecar in &data.sidecars {
+                    println!("    sidecar {} {} ({})", sidecar.hash, sidecar.path, sidecar.keys.join(", "));
+                }
+                print_md(&data.md);
+            } else if let Some(transform) = db.get_transform(id) {
+                println!("{}", transform);
+                println!("    uid {}", transform.uid);
+                for (path, hash) in transform.script_paths.iter().zip(&transform.script_git_hashes) {
+                    println!("    commit {} {}", hash.as_deref().unwrap_or("(not tracked by git)"), path);
+                }
+                print_md(&transform.md);
+            } else if let Some(connection) = db.get_connection(id) {
+                println!("{}", connection);
+                println!("    uid {}", connection.uid);
+                print_md(&connection.md);
+                for in_id in connection.in_ids() {
+                    println!("    in {}", describe_entry(&db, in_id));
+                }
+                for out_id in connection.out_ids() {
+                    println!("    out {}", describe_entry(&db, out_id));
+                }
+                return Ok(());
+            } else {
+                println!("Error: Entry with id {} does not exist", id);
+                return Err(format!("Entry with id {} does not exist", id).into());
+            }
+            for connection in db.in_connections(id) {
+                println!("    made by {}", connection);
+            }
+            for connection in db.out_connections(id) {
+                println!("    used by {}", connection);
+            }
+        }
+        Commands::Lineage(Lineage{db_path, id, as_of}) => {
+            let db = load_as_of(db_path.as_deref(), as_of.as_deref())?;
+            if db.get_data(id).is_none() && db.get_transform(id).is_none() {
+                println!("Error: Data or transform with id {} does not exist", id);
+                return Err(format!("Data or transform with id {} does not exist", id).into());
+            }
+            print_lineage(&db, id, 0, &mut std::collections::HashSet::new());
+        }
+        Commands::Graph(Graph{db_path, as_of}) => {
+            let db = load_as_of(db_path.as_deref(), as_of.as_deref())?;
+            println!("{}", db.to_dot());
+        }
+        Commands::Diff(Diff{db_path, from, to}) => {
+            let before = Database::at(db_path.as_deref(), &from)?;
+            let after = load_as_of(db_path.as_deref(), to.as_deref())?;
+            let changes = before.diff(&after);
+            if changes.is_empty() {
+                println!("No changes");
+            }
+            for change in changes {
+                println!("{}", change);
+            }
+        }
+        Commands::Undo(Undo{db_path, n}) => {
+            let mut db = Database::load(db_path.as_deref())?;
+            let events = db.undo(n)?;
+            db.write()?;
+            for event in events {
+                println!("Undid {}", event);
+                for change in &event.changes {
+                    println!("    {}", change);
+                }
+                for m in &event.moves {
+                    println!("    moved {} to {}", m.src, m.dst);
+                }
+            }
+        }
     }
 
     Ok(())
@@ -5,7 +5,7 @@
 
 use tauri::State;
 use std::sync::Mutex;
-use psidb_lib::database::Database;
+use psidb_lib::database::{Database, template::Template, entry::transform::Transform};
 
 struct AppData {
     db_path: String,
@@ -82,14 +82,18 @@ fn add_data(state: AppState, data_paths: Vec<&str>, meta_data_str: &str) -> bool
 }
 
 #[tauri::command]
-fn add_transform(state: AppState, script_paths: Vec<&str>, script_args_str: &str, meta_data_str: &str) -> bool {
+fn add_transform(state: AppState, script_paths: Vec<&str>, script_args: Vec<&str>, meta_data_str: &str) -> bool {
     let mut data = state.lock().unwrap();
 
     if data.db.is_none() {
         return false;
     }
+    let script_args = Transform::split_args(&script_args);
+    if script_args.is_err() {
+        return false;
+    }
     let db = data.db.as_mut().unwrap();
-    if db.add_transform(&script_paths, Some(script_args_str), None, Some(meta_data_str)).is_err() {
+    if db.add_transform(&script_paths, Some(&script_args.unwrap()), None, Some(meta_data_str)).is_err() {
         return false;
     }
     db.write().is_ok()
@@ -131,7 +135,7 @@ fn apply(state: AppState, transform_id: u64, data_ids: Vec<u64>, meta_data_str:
         return false;
     }
     let db = data.db.as_mut().unwrap();
-    if db.apply(transform_id, &data_ids, Some(meta_data_str)).is_err() {
+    if db.apply(transform_id, &data_ids, Some(meta_data_str), false, false).is_err() {
         return false;
     }
     db.write().is_ok()
@@ -150,6 +154,7 @@ fn connect(state: AppState, action: &str, in_data_ids: Vec<u64>, out_data_ids: V
         "Apply" => Some(psidb_lib::database::entry::action::Action::Apply),
         "Chain" => Some(psidb_lib::database::entry::action::Action::Chain),
         "Link" => Some(psidb_lib::database::entry::action::Action::Link),
+        "Update" => Some(psidb_lib::database::entry::action::Action::Update),
         _ => None
     };
     if action.is_none() {
@@ -185,6 +190,34 @@ fn connect(state: AppState, action: &str, in_data_ids: Vec<u64>, out_data_ids: V
     db.write().is_ok()
 }
 
+#[tauri::command]
+fn undo(state: AppState, n: usize) -> Result<String, String> {
+    let mut data = state.lock().unwrap();
+
+    if data.db.is_none() {
+        return Err("No database loaded".to_owned());
+    }
+    let db = data.db.as_mut().unwrap();
+    let events = db.undo(n).map_err(|e| e.to_string())?;
+    db.write().map_err(|e| e.to_string())?;
+
+    let undone: Vec<String> = events.iter().map(|e| e.invocation.clone()).collect();
+    Ok(undone.join("\n"))
+}
+
+#[tauri::command]
+fn get_templates(state: AppState, kind: &str) -> Result<(Vec<Template>, Option<String>), String> {
+    let data = state.lock().unwrap();
+
+    if data.db.is_none() {
+        return Err("No database loaded".to_owned());
+    }
+    // The templates and the name of the default one for this kind of entry (data or transform)
+    let db = data.db.as_ref().unwrap();
+    let default = db.get_setting(&format!("{}_template", kind)).map_err(|e| e.to_string())?;
+    Ok((db.get_templates().to_vec(), default))
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let db_path = Database::get_psidb_dir(None).into_os_string().into_string().unwrap();
     let db = if let Ok(db) = Database::load(None) {
@@ -210,7 +243,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             link,
             chain,
             apply,
-            connect
+            connect,
+            undo,
+            get_templates
         ])
         .run(tauri::generate_context!())?;
     Ok(())
@@ -20,6 +20,16 @@
     apply_transform = () => {goto("/add_data/apply_transform/index.html")};
     init_db = () => {goto("/add_data/init_db/index.html")};
     connect = () => {goto("/add_data/connect/index.html")};
+    undo = async () => {
+        const invoke = window.__TAURI__.invoke;
+        const message = window.__TAURI__.dialog.message;
+        try {
+            const undone = await invoke("undo", {n: 1});
+            message(`Undid:\n${undone}`, {type: "info"});
+        } catch (error) {
+            message(`Error: ${error}`, {type: "error"});
+        }
+    };
     
     const container = document.getElementById("btn-container");
     if (await is_db_loaded()) {
@@ -41,6 +51,9 @@
         
         const connect_btn = create_button("Connect Entries", connect, 2);
         container.appendChild(connect_btn);
+
+        const undo_btn = create_button("Undo Last Change", undo, 2);
+        container.appendChild(undo_btn);
     }
     else {
         // Could not load the database, warn the user and invite them to initialize the database
@@ -14,7 +14,7 @@
         const args_input = document.createElement("input");
         args_input.setAttribute("name", "args");
         args_input.setAttribute("type", "text");
-        args_input.setAttribute("placeholder", "Script arguments");
+        args_input.setAttribute("placeholder", "Script arguments (shell quoting)");
 
         const btn = document.createElement("button");
         btn.innerText = "Remove";
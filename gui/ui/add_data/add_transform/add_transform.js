@@ -7,7 +7,7 @@
             const input = Array.from(entry.children).find(elem => elem.tagName == "INPUT");
             args.push(input.value);
         }
-        return args.join(";");
+        return args;
     }
 
     async function try_add_transform() {
@@ -23,7 +23,7 @@
             return;
         }
         const invoke = window.__TAURI__.invoke;
-        const did_add = await invoke("add_transform", {scriptPaths: paths, scriptArgsStr: args, metaDataStr: md});
+        const did_add = await invoke("add_transform", {scriptPaths: paths, scriptArgs: args, metaDataStr: md});
 
         const message = window.__TAURI__.dialog.message;
         if (!did_add) {
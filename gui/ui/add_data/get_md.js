@@ -1,3 +1,12 @@
+// Quotes a key or value that psidb would otherwise split, trim or unescape (`key:type` stays as is)
+function quote_md(s) {
+    if (s !== "" && s.trim() === s && !/[;="'\\\n\t]/.test(s) && !s.startsWith("@")) {
+        return s;
+    }
+    const escaped = s.replace(/\\/g, "\\\\").replace(/"/g, "\\\"").replace(/\n/g, "\\n").replace(/\t/g, "\\t");
+    return `"${escaped}"`;
+}
+
 async function get_md() {
     let kv_arr = [];
     let should_warn = false;
@@ -7,6 +16,9 @@ async function get_md() {
         const elems = Array.from(kv_container.children);
         const key = elems.find(elem => elem.classList.contains("key")).value ?? "";
         const value = elems.find(elem => elem.classList.contains("value")).value ?? "";
+        if (kv_container.classList.contains("template-entry") && value === "") {
+            continue; // Optional template fields can be left out, psidb reports the missing required ones
+        }
         if (key === "" || value === "") {
             should_warn = true;
         }
@@ -24,6 +36,6 @@ async function get_md() {
     }
 
     // Convert the array to a string that psidb can parse
-    const md = kv_arr.map(kv => kv.join("=")).join(";");
+    const md = kv_arr.map(([key, value]) => `${quote_md(key)}=${quote_md(value)}`).join(";");
     return [md, md_ok];
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+(async function () { // Wrap everything in a closure
+    const select = document.getElementById("template-select");
+    const form = document.getElementById("md-form");
+
+    function value_to_string(value) {
+        if (Array.isArray(value)) {
+            return value.map(value_to_string).join(",");
+        }
+        // Dates come as {"$datetime": "<RFC 3339 date>"}
+        if (value !== null && typeof value === "object" && "$datetime" in value) {
+            return value["$datetime"];
+        }
+        return String(value);
+    }
+
+    function make_template_entry(field) {
+        const key_input = document.createElement("input");
+        key_input.setAttribute("type", "text");
+        key_input.classList.add("key");
+        key_input.value = field.key;
+        key_input.readOnly = true;
+
+        let value_input;
+        if (field.allowed.length > 0) {
+            // Only the allowed values can be picked
+            value_input = document.createElement("select");
+            const values = field.required ? field.allowed : [""].concat(field.allowed);
+            for (const value of values) {
+                const option = document.createElement("option");
+                option.value = value_to_string(value);
+                option.innerText = value_to_string(value);
+                value_input.appendChild(option);
+            }
+        } else {
+            value_input = document.createElement("input");
+            value_input.setAttribute("type", "text");
+            value_input.placeholder = field.regex ?? field.type ?? "";
+        }
+        value_input.classList.add("value");
+        value_input.required = field.required;
+        if (field.default !== null) {
+            value_input.value = value_to_string(field.default);
+        }
+
+        const kv_container = document.createElement("div");
+        kv_container.classList.add("kv-entry");
+        kv_container.classList.add("template-entry");
+        kv_container.title = field.required ? `${field.key} is required` : `${field.key} is optional`;
+        kv_container.appendChild(key_input);
+        kv_container.appendChild(value_input);
+        return kv_container;
+    }
+
+    function show_template(templates) {
+        // Replace the entries of the previous template, but keep the ones the user added
+        for (const entry of Array.from(form.getElementsByClassName("template-entry"))) {
+            entry.remove();
+        }
+        const template = templates.find(t => t.name === select.value);
+        if (template === undefined) {
+            return;
+        }
+
+        const entries = template.fields.map(make_template_entry);
+
+        // Name the template so that psidb checks the metadata against it
+        const name_entry = make_template_entry({key: "template", allowed: [], required: true, default: template.name, regex: null, type: null});
+        name_entry.hidden = true;
+        entries.unshift(name_entry);
+        form.prepend(...entries);
+    }
+
+    if (!await is_db_loaded()) {
+        return;
+    }
+    const invoke = window.__TAURI__.invoke;
+    let templates, default_name;
+    try {
+        [templates, default_name] = await invoke("get_templates", {kind: select.dataset.kind});
+    } catch (error) {
+        return;
+    }
+
+    // Without a default template, metadata can also be entered freely
+    if (default_name === null) {
+        const option = document.createElement("option");
+        option.value = "";
+        option.innerText = "(no template)";
+        select.appendChild(option);
+    }
+    for (const template of templates) {
+        const option = document.createElement("option");
+        option.value = template.name;
+        option.innerText = template.name;
+        select.appendChild(option);
+    }
+    select.value = default_name ?? "";
+    select.hidden = templates.length === 0;
+    select.addEventListener("change", () => show_template(templates));
+    show_template(templates);
+})();
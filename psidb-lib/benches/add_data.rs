@@ -0,0 +1,57 @@
+//! Adds many single-file datasets to a fresh database and reports how long each batch takes, which should stay flat as
+//! the database grows. Run with `cargo bench -p psidb-lib`, and set PSIDB_BENCH_N to change the number of datasets
+//! (100000 by default).
+
+use std::fs;
+use std::time::Instant;
+use psidb_lib::database::Database;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let n: usize = std::env::var("PSIDB_BENCH_N").ok().and_then(|n| n.parse().ok()).unwrap_or(100_000);
+    let batch = (n / 10).max(1);
+
+    let dir = std::env::temp_dir().join(format!("psidb-bench-{}", std::process::id()));
+    let files_dir = dir.join("files");
+    fs::create_dir_all(&files_dir)?;
+    let dir_str = dir.to_str().unwrap().to_owned();
+
+    let start = Instant::now();
+    let paths: Vec<String> = (0..n).map(|i| {
+        let path = files_dir.join(format!("{}.txt", i));
+        fs::write(&path, i.to_string()).unwrap();
+        path.to_str().unwrap().to_owned()
+    }).collect();
+    println!("Wrote {} files in {:.2?}", n, start.elapsed());
+
+    Database::init(Some(&dir_str))?;
+    let mut db = Database::load(Some(&dir_str))?;
+
+    let start = Instant::now();
+    let mut batch_start = Instant::now();
+    for (i, path) in paths.iter().enumerate() {
+        db.add_data(&[path], None)?;
+        if (i + 1) % batch == 0 {
+            println!("Added datasets {:>7} to {:>7} in {:.2?}", i + 2 - batch, i + 1, batch_start.elapsed());
+            batch_start = Instant::now();
+        }
+    }
+    println!("Added {} datasets in {:.2?}", n, start.elapsed());
+
+    // Adding a dataset that is already there has to find it among all the others
+    let start = Instant::now();
+    for path in paths.iter().step_by(batch) {
+        assert!(db.add_data(&[path], None).is_err());
+    }
+    println!("Rejected {} duplicates in {:.2?}", paths.iter().step_by(batch).count(), start.elapsed());
+
+    let start = Instant::now();
+    db.write()?;
+    println!("Wrote the database in {:.2?}", start.elapsed());
+
+    let start = Instant::now();
+    let db = Database::load(Some(&dir_str))?;
+    println!("Loaded the database ({} datasets) in {:.2?}", db.get_data_vec().len(), start.elapsed());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -1,22 +1,69 @@
 pub mod entry;
+pub mod settings;
+pub mod graph;
+pub mod status;
+pub mod apply;
+pub mod update;
+pub mod pipeline;
+pub mod merge;
+pub mod journal;
+pub mod edit;
+pub mod undo;
+pub mod query;
+pub mod template;
+pub mod index;
+pub mod import;
+pub mod sidecar;
+pub mod extract;
+pub mod roots;
+pub mod relocate;
+pub mod fsck;
+pub mod migrate;
+pub mod store;
 use std::fs;
+use std::io::BufWriter;
 use std::error::Error;
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 use serde::{Serialize, Deserialize};
 use ron::ser::{PrettyConfig, to_writer_pretty};
-use chrono::{Utc, SecondsFormat};
-use entry::{data::Data, transform::Transform, connection::Connection, action::Action};
+use entry::{data::Data, transform::Transform, connection::Connection, action::Action, tree::TreeFilter};
+use entry::metadata::{Metadata, MetaValue, parser};
+use settings::Settings;
 use super::utils;
-use entry::{entry_in, id_in};
+use entry::new_uid;
+use index::Index;
+
+// The version of the layout of db.ron, databases with an older version are migrated when they are loaded (see
+// `migrate.rs`)
+pub const FORMAT_VERSION: u32 = migrate::MIGRATIONS.len() as u32;
 
 #[derive(Serialize, Deserialize)]
 pub struct Database {
-    db_path: String,
+    #[serde(default)]
+    format_version: u32,
+    #[serde(skip_deserializing)]
+    db_path: String, // Where the database was loaded from (still written, for older versions of psidb to read)
     data_vec: Vec<Data>,
     transform_vec: Vec<Transform>,
     connection_vec: Vec<Connection>,
-    curr_id: u64
+    curr_id: u64,
+    #[serde(default)]
+    settings: Settings,
+    #[serde(skip)]
+    journal_base: journal::Snapshot, // What the log already records, to find what changed
+    #[serde(skip)]
+    pending_undoes: Vec<u64>, // The events that the next log event reverts
+    #[serde(skip)]
+    pending_moves: Vec<journal::Move>, // The moves on disk that the next log event records
+    #[serde(skip)]
+    read_only: Option<String>, // Why the database cannot be written (a past view made by `at`, or an older database read by `read_from`)
+    #[serde(skip)]
+    index: Index,
+    #[serde(skip)]
+    extractors: Vec<Box<dyn extract::MetadataExtractor>>, // The extractors registered by the program using the library
+    #[serde(skip)]
+    local_roots: BTreeMap<String, String> // Where the roots are on this machine (from the local config)
 }
 
 impl Database {
@@ -32,17 +79,31 @@ impl Database {
             return Err(Box::new(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} already exists", db_path.to_str().unwrap()))));
         }
 
-        Ok(Database {
+        Ok(Self::new_unchecked(&db_path))
+    }
+
+    // An empty database stored at db_path
+    fn new_unchecked(db_path: &Path) -> Database {
+        Database {
+            format_version: FORMAT_VERSION,
             db_path: db_path.to_str().unwrap().to_owned(),
             data_vec: Vec::new(),
             transform_vec: Vec::new(),
             connection_vec: Vec::new(),
-            curr_id: 0
-        })
+            curr_id: 0,
+            settings: Settings::default(),
+            journal_base: journal::Snapshot::default(),
+            pending_undoes: Vec::new(),
+            pending_moves: Vec::new(),
+            read_only: None,
+            index: Index::default(),
+            extractors: Vec::new(),
+            local_roots: BTreeMap::new()
+        }
     }
 
     pub fn init(path_str: Option<&str>) -> Result<(), Box<dyn Error>> {
-        let db = Database::new(path_str)?;
+        let mut db = Database::new(path_str)?;
         db.write()?;
         println!("Created database at {}", db.db_path);
         Ok(())
@@ -56,9 +117,23 @@ impl Database {
             println!("Error: The database does not exist. Create one with `psidb --init` or use the flag `--db <path>` to specify the location of the database.");
             return Err(Box::new(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} does not exist", db_path.to_str().unwrap()))));
         }
+        Self::load_from(&db_path)
+    }
 
-        let db_str = fs::read_to_string(&db_path)?;
-        let db: Database = ron::from_str(&db_str)?;
+    /// Loads the database stored in the given db.ron file
+    pub fn load_from(db_path: &Path) -> Result<Database, Box<dyn Error>> {
+        Self::read_migrated(db_path)
+    }
+
+    // Reads the contents of the db.ron file at db_path, with the current format version
+    fn parse(db_str: &str, db_path: &Path) -> Result<Database, Box<dyn Error>> {
+        let mut db: Database = ron::from_str(db_str)?;
+        // The database may have been copied or moved since it was written
+        db.db_path = std::path::absolute(db_path)?.to_str().unwrap().to_owned();
+        db.local_roots = roots::LocalConfig::load()?.roots;
+        db.map_all_paths(false);
+        db.reindex();
+        db.journal_base = db.snapshot();
         Ok(db)
     }
 
@@ -83,31 +158,86 @@ impl Database {
         self.db_path.clone()
     }
 
-    pub fn write(&self) -> Result<(), Box<dyn Error>> {
+    pub fn get_data_vec(&self) -> &[Data] {
+        &self.data_vec
+    }
+
+    pub fn get_transform_vec(&self) -> &[Transform] {
+        &self.transform_vec
+    }
+
+    pub fn get_connection_vec(&self) -> &[Connection] {
+        &self.connection_vec
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        self.settings.get(key)
+    }
+
+    pub fn set_setting(&mut self, key: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.settings.set(key, value)
+    }
+
+    pub fn get_data_root(&self) -> PathBuf {
+        // Relative data roots are relative to the folder that contains .psidb/
+        let psidb_dir = Path::new(&self.db_path).parent().unwrap();
+        match &self.settings.data_root {
+            Some(data_root) => psidb_dir.parent().unwrap_or(psidb_dir).join(data_root),
+            None => psidb_dir.join("data")
+        }
+    }
+
+    /// Saves the database and records what changed since it was loaded in the operation log
+    pub fn write(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(reason) = &self.read_only {
+            println!("Error: {}", reason);
+            return Err(reason.clone().into());
+        }
+        self.write_file()?;
+        self.journal()
+    }
+
+    // Saves db.ron without recording anything in the log
+    fn write_file(&mut self) -> Result<(), Box<dyn Error>> {
         let serde_conf = PrettyConfig::new()
             .depth_limit(5)
             .indentor("\t".to_owned())
             .struct_names(true);
 
-        let mut file = fs::File::create(&self.db_path)?;
-        to_writer_pretty(&mut file, self, serde_conf)?;
-
+        // Paths under roots are stored relative to them
+        self.map_all_paths(true);
+        // The new contents replace db.ron in one step once they are on disk, so a crash cannot leave it half written
+        let tmp_path = Path::new(&self.db_path).with_extension("ron.tmp");
+        let written = fs::File::create(&tmp_path).map_err(Box::<dyn Error>::from).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            to_writer_pretty(&mut writer, self, serde_conf)?;
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            Ok(())
+        });
+        self.map_all_paths(false);
+        if !self.settings.roots.is_empty() {
+            self.reindex();
+        }
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        fs::rename(&tmp_path, &self.db_path)?;
         Ok(())
     }
 
-    fn parse_md(meta_data_str: Option<&str>) -> HashMap<String, String> {
-        if meta_data_str.is_none() {
-            return HashMap::new()
+    fn parse_md(meta_data_str: Option<&str>) -> Result<Metadata, Box<dyn Error>> {
+        match meta_data_str {
+            Some(s) => parser::parse(s),
+            None => Ok(HashMap::new())
         }
-        let mut md = HashMap::new();
-        for s in meta_data_str.unwrap().split(';') {
-            let args: Vec<&str> = s.split('=').collect();
-            md.insert(args[0].to_owned(), args[1..].join("="));
-        };
-        md
     }
 
-    fn try_add_data(&mut self, mut data: Data) -> Result<u64, Box<dyn Error>> {
+    fn try_add_data(&mut self, data: Data) -> Result<u64, Box<dyn Error>> {
+        self.try_insert_data(data, None)
+    }
+
+    fn try_insert_data(&mut self, mut data: Data, reserved_id: Option<u64>) -> Result<u64, Box<dyn Error>> {
         // Make sure the data is not empty
         if data.paths.is_empty() {
             println!("Error: No data paths");
@@ -115,29 +245,63 @@ impl Database {
         }
 
         // Check if the data already exists in the database
-        match entry_in(&data, &self.data_vec) {
-            (true, id) => {
-                println!("Error: Data with the same paths already exists at id {}", id);
-                return Err(format!("Data with the same paths already exists at id {}", id).into());
-            }
-            (false, ..) => {}
+        if let Some(id) = self.find_data(&data) {
+            println!("Error: Data with the same paths already exists at id {}", id);
+            return Err(format!("Data with the same paths already exists at id {}", id).into());
         }
-        data.id = self.curr_id;
+        // Use the id that was set aside for this data (e.g. for a run in progress) or the next free id
+        data.id = reserved_id.unwrap_or(self.curr_id);
+
+        // Record the content hashes so that we can tell when the data changes
+        data.hash()?;
 
         // Add the data to the database
-        self.data_vec.push(data);
-        self.curr_id += 1;
+        let id = data.id;
+        self.push_data(data);
+        if reserved_id.is_none() {
+            self.curr_id += 1;
+        }
 
-        Ok(self.curr_id - 1)
+        Ok(id)
     }
 
     pub fn add_data<T> (&mut self, data_paths: &[T], meta_data_str: Option<&str>) -> Result<u64, Box<dyn Error>> 
+    where T: AsRef<str> + AsRef<std::ffi::OsStr> + std::fmt::Display {
+        self.add_filtered_data(data_paths, meta_data_str, TreeFilter::default(), None)
+    }
+
+    /// Adds data like `add_data`, tracking only the files of its directories that the filter selects. With store, the
+    /// files are moved into the object store, or not, whatever the `store` setting says.
+    pub fn add_filtered_data<T> (&mut self, data_paths: &[T], meta_data_str: Option<&str>, filter: TreeFilter, store: Option<bool>) -> Result<u64, Box<dyn Error>> 
+    where T: AsRef<str> + AsRef<std::ffi::OsStr> + std::fmt::Display {
+        let md = Self::parse_md(meta_data_str)?;
+        let mut data = self.make_data(data_paths, md)?;
+        data.filter = filter;
+        self.add_new_data(data, store)
+    }
+
+    // Adds data made by `make_data`, once its metadata follows the data template. With store, the files are moved into
+    // the object store, or not, whatever the `store` setting says.
+    fn add_new_data(&mut self, mut data: Data, store: Option<bool>) -> Result<u64, Box<dyn Error>> {
+        self.apply_template(&mut data.md, self.settings.data_template.as_ref())?;
+
+        // Add the data to the database
+        let id = self.try_add_data(data)?;
+        if store.unwrap_or(self.settings.store) {
+            self.store_data(id)?;
+        }
+        Ok(id)
+    }
+
+    fn make_data<T> (&self, data_paths: &[T], md: Metadata) -> Result<Data, Box<dyn Error>> 
     where T: AsRef<str> + AsRef<std::ffi::OsStr> + std::fmt::Display {
         // Check if the paths are valid and make the paths aboslute paths
         let mut used_paths: Vec<String> = vec!["".to_owned(); data_paths.len()];
         for (i, path) in data_paths.iter().enumerate() {
-            if Path::new(path).exists() {
-                used_paths[i] = Path::new(path).canonicalize()?.to_str().unwrap().to_owned();
+            // Paths can be given relative to a root, e.g. `$scratch/run1`
+            let path = self.resolve_path(path.as_ref());
+            if Path::new(&path).exists() {
+                used_paths[i] = store::canonicalize(Path::new(&path))?.to_str().unwrap().to_owned();
             }
             else {
                 println!("Error: Path {} does not exist", path);
@@ -145,21 +309,27 @@ impl Database {
             }
         }
 
-        // Add the current time to the meta data if it doesn't already exist
-        let mut md = Self::parse_md(meta_data_str);
-        if !md.contains_key("time") {
-            md.insert("time".to_owned(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
-        }
-
-        // Construct the data
-        let data = Data {
+        // Construct the data, with the metadata of its sidecars and of the enabled extractors
+        let mut data = Data {
             id: self.curr_id,
+            uid: new_uid(),
             md,
-            paths: used_paths
+            paths: used_paths,
+            hashes: vec![],
+            sizes: vec![],
+            sidecars: vec![],
+            filter: TreeFilter::default(),
+            trees: vec![],
+            stored: false
         };
+        self.read_sidecars(&mut data)?;
+        self.extract_md(&mut data)?;
 
-        // Add the data to the database
-        self.try_add_data(data)
+        // Add the current time to the meta data if it doesn't already exist
+        if !data.md.contains_key("time") {
+            data.md.insert("time".to_owned(), MetaValue::now());
+        }
+        Ok(data)
     }
 
     fn try_add_transform(&mut self, mut transform: Transform) -> Result<u64, Box<dyn Error>> {
@@ -170,25 +340,33 @@ impl Database {
         }
 
         // Check if the transform already exists in the database
-        match entry_in(&transform, &self.transform_vec) {
-            (true, id) => {
-                println!("Error: Transform with the same name already exists at id {}", id);
-                return Err(format!("Transform with the same name already exists at id {}", id).into());
-            }
-            (false, ..) => {}
+        if let Some(id) = self.find_transform(&transform) {
+            println!("Error: Transform with the same name already exists at id {}", id);
+            return Err(format!("Transform with the same name already exists at id {}", id).into());
         }
         transform.id = self.curr_id;
 
         // Add the transform to the database
-        self.transform_vec.push(transform);
+        self.push_transform(transform);
         self.curr_id += 1;
 
         Ok(self.curr_id - 1)
     }
 
-    pub fn add_transform<T>(&mut self, script_paths: &[T], script_args_str: Option<&str>, script_git_hashes_str: Option<&str>, meta_data_str: Option<&str>) -> Result<u64, Box<dyn Error>>
+    pub fn add_transform<T>(&mut self, script_paths: &[T], script_args: Option<&[Vec<String>]>, script_git_hashes_str: Option<&str>, meta_data_str: Option<&str>) -> Result<u64, Box<dyn Error>>
+    where T: AsRef<str> + AsRef<std::ffi::OsStr> + std::fmt::Display {
+        let mut md = Self::parse_md(meta_data_str)?;
+        self.apply_template(&mut md, self.settings.transform_template.as_ref())?;
+        let transform = self.make_transform(script_paths, script_args, script_git_hashes_str, md)?;
+
+        // Add the transform
+        self.try_add_transform(transform)
+    }
+
+    fn make_transform<T>(&self, script_paths: &[T], script_args: Option<&[Vec<String>]>, script_git_hashes_str: Option<&str>, mut md: Metadata) -> Result<Transform, Box<dyn Error>>
     where T: AsRef<str> + AsRef<std::ffi::OsStr> + std::fmt::Display {
-        let script_args = utils::parse_kv_opt_string(script_args_str, Some(script_paths.len()));
+        // Scripts without arguments get an empty argument list
+        let script_args = script_args.map(<[Vec<String>]>::to_vec).unwrap_or_else(|| vec![vec![]; script_paths.len()]);
         let script_git_hashes = utils::parse_kv_opt_string(script_git_hashes_str, Some(script_paths.len()));
 
         // Make sure that the script_paths, script_args, and script_git_hashes are the same length
@@ -202,10 +380,11 @@ impl Database {
         let mut used_paths: Vec<String> = vec!["".to_owned(); script_paths.len()];
         for (i, (path_str, hash)) in script_paths.iter().zip(script_git_hashes).enumerate() {
             // Make sure the script exists and is a file
-            utils::verify_file_path(path_str)?;
+            let path_str = self.resolve_path(path_str.as_ref());
+            utils::verify_file_path(&path_str)?;
 
             // Get the absolute path of the script
-            let path = Path::new(path_str);
+            let path = Path::new(&path_str);
             used_paths[i] = path.canonicalize()?.to_str().unwrap().to_owned();
 
             // Check if the file is in a git repository
@@ -242,21 +421,18 @@ impl Database {
         }
 
         // Add the current time to the meta data if it doesn't already exist
-        let mut md = Self::parse_md(meta_data_str);
         if !md.contains_key("time") {
-            md.insert("time".to_owned(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
+            md.insert("time".to_owned(), MetaValue::now());
         }
 
-        let transform = Transform {
+        Ok(Transform {
             id: self.curr_id,
+            uid: new_uid(),
             md,
             script_paths: used_paths,
             script_args,
             script_git_hashes: used_hashes
-        };
-
-        // Add the transform
-        self.try_add_transform(transform)
+        })
     }
 
     pub fn connect(&mut self, action: Action, in_data_ids: Option<&[u64]>, out_data_ids: Option<&[u64]>, in_transform_ids: Option<&[u64]>, out_transform_ids: Option<&[u64]>, meta_data_str: Option<&str>) -> Result<u64, Box<dyn Error>> {
@@ -273,13 +449,13 @@ impl Database {
         
         // Check to see if all the data_ids exist in the database
         for id in in_data_ids {
-            if !id_in(*id, &self.data_vec) {
+            if self.get_data(*id).is_none() {
                 println!("Error: Input data with id {} does not exist", id);
                 return Err(format!("Input data with id {} does not exist", id).into());
             }
         }
         for id in out_data_ids {
-            if !id_in(*id, &self.data_vec) {
+            if self.get_data(*id).is_none() {
                 println!("Error: Output data with id {} does not exist", id);
                 return Err(format!("Output data with id {} does not exist", id).into());
             }
@@ -287,96 +463,57 @@ impl Database {
 
         // Check to see if all the transform_ids exist in the database
         for id in in_transform_ids {
-            if !id_in(*id, &self.transform_vec) {
+            if self.get_transform(*id).is_none() {
                 println!("Error: Input transform with id {} does not exist", id);
                 return Err(format!("Input transform with id {} does not exist", id).into());
             }
         }
         for id in out_transform_ids {
-            if !id_in(*id, &self.transform_vec) {
+            if self.get_transform(*id).is_none() {
                 println!("Error: Output transform with id {} does not exist", id);
                 return Err(format!("Output transform with id {} does not exist", id).into());
             }
         }
 
         // Add the current time to the meta data if it doesn't already exist
-        let mut md = Self::parse_md(meta_data_str);
+        let mut md = Self::parse_md(meta_data_str)?;
         if !md.contains_key("time") {
-            md.insert("time".to_owned(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
+            md.insert("time".to_owned(), MetaValue::now());
         }
 
         let connection = Connection {
             id: self.curr_id,
+            uid: new_uid(),
             md,
             action,
             in_data_ids: in_data_ids.to_vec(),
             out_data_ids: out_data_ids.to_vec(),
             in_transform_ids: in_transform_ids.to_vec(),
-            out_transform_ids: out_transform_ids.to_vec()
+            out_transform_ids: out_transform_ids.to_vec(),
+            in_data_hashes: vec![]
         };
 
+        self.try_add_connection(connection)
+    }
+
+    fn try_add_connection(&mut self, mut connection: Connection) -> Result<u64, Box<dyn Error>> {
         // Check if the connection already exists in the database
-        match entry_in(&connection, &self.connection_vec) {
-            (true, id) => {
-                println!("Error: Connection with the same action, data, and transforms already exists at id {}", id);
-                return Err(format!("Connection with the same action, data and transforms already exists at id {}", id).into());
-            }
-            (false, ..) => {}
+        if let Some(id) = self.find_connection(&connection) {
+            println!("Error: Connection with the same action, data, and transforms already exists at id {}", id);
+            return Err(format!("Connection with the same action, data and transforms already exists at id {}", id).into());
         }
+        connection.id = self.curr_id;
 
-        self.connection_vec.push(connection);
+        self.push_connection(connection);
         self.curr_id += 1;
 
         Ok(self.curr_id - 1)
     }
 
-    pub fn apply(&mut self, transform_id: u64, data_ids: &[u64], meta_data_str: Option<&str>) -> Result<(u64, u64), Box<dyn Error>> {
-        // Check if the transform exists
-        if !id_in(transform_id, &self.transform_vec) {
-            println!("Error: Transform with id {} does not exist", transform_id);
-            return Err(format!("Transform with id {} does not eixst", transform_id).into());
-        }
-
-        // Check if the data ids exist
-        for id in data_ids {
-            if !id_in(*id, &self.data_vec) {
-                println!("Error: Data with id {} does not exist", *id);
-                return Err(format!("Data with id {} does not eixst", *id).into());
-            }
-        }
-
-        // Get the transform
-        let transform = self.transform_vec.iter().find(|t| t.id == transform_id).unwrap();
-        
-        // Get the data
-        let mut data = Vec::with_capacity(data_ids.len());
-        for id in data_ids {
-            data.push(self.data_vec.iter().find(|d| d.id == *id).unwrap());
-        }
-
-        // Apply the scripts in the transform sequentially
-        let mut new_data = transform.apply(&data, self.curr_id)?;
-
-        // Add the current time to the meta data if it doesn't already exist
-        let mut md = Self::parse_md(meta_data_str);
-        if !md.contains_key("time") {
-            md.insert("time".to_owned(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
-        }
-        new_data.md = md;
-
-        // Add the data to the database
-        let new_data_id = self.try_add_data(new_data)?;
-
-        // Connect the new data to the transform
-        let new_connect_id = self.connect(Action::Apply, Some(data_ids), Some(&[new_data_id]), Some(&[transform_id]), None, meta_data_str)?;
-
-        Ok((new_data_id, new_connect_id))
-    }
-
     pub fn chain(&mut self, transform_ids: &[u64], meta_data_str: Option<&str>) -> Result<(u64, u64), Box<dyn Error>> {
         // Check if all the transforms exist
         for id in transform_ids {
-            if !id_in(*id, &self.transform_vec) {
+            if self.get_transform(*id).is_none() {
                 println!("Error: Transform with id {} does not exist", *id);
                 return Err(format!("Transform with id {} does not eixst", *id).into());
             }
@@ -385,14 +522,13 @@ impl Database {
         // Get the transforms
         let transforms = transform_ids
             .iter()
-            .map(|id| self.transform_vec.iter().find(|t| t.id == *id)
-            .unwrap())
+            .map(|id| self.get_transform(*id).unwrap())
             .collect::<Vec<&Transform>>();
 
         // The metadata for this new transform
-        let mut given_md = Self::parse_md(meta_data_str);
+        let mut given_md = Self::parse_md(meta_data_str)?;
         if !given_md.contains_key("time") {
-            given_md.insert("time".to_owned(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
+            given_md.insert("time".to_owned(), MetaValue::now());
         }
 
         let mut md = HashMap::new();
@@ -410,6 +546,7 @@ impl Database {
         // Create the new transform
         let transform = Transform {
             id: self.curr_id,
+            uid: new_uid(),
             md,
             script_paths,
             script_args,
@@ -428,7 +565,7 @@ impl Database {
     pub fn link(&mut self, data_ids: &[u64], meta_data_str: Option<&str>) -> Result<(u64, u64), Box<dyn Error>> {
         // Check if the data ids exist
         for id in data_ids {
-            if !id_in(*id, &self.data_vec) {
+            if self.get_data(*id).is_none() {
                 println!("Error: Data with id {} does not exist", *id);
                 return Err(format!("Data with id {} does not eixst", *id).into());
             }
@@ -437,28 +574,39 @@ impl Database {
         // Get the data
         let all_data = data_ids
             .iter()
-            .map(|id| self.data_vec.iter().find(|d| d.id == *id).unwrap())
+            .map(|id| self.get_data(*id).unwrap())
             .collect::<Vec<&Data>>();
         
         // The metadata for this new transform
-        let mut given_md = Self::parse_md(meta_data_str);
+        let mut given_md = Self::parse_md(meta_data_str)?;
         if !given_md.contains_key("time") {
-            given_md.insert("time".to_owned(), Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true));
+            given_md.insert("time".to_owned(), MetaValue::now());
         }
 
         let mut md = HashMap::new();
         let mut paths = vec![];
+        let mut sidecars = vec![];
         for data in &all_data {
             md.extend(data.md.iter().map(|(k, v)| (k.clone(), v.clone())));
             paths.extend(data.paths.iter().cloned());
+            sidecars.extend(data.sidecars.iter().cloned());
         }
+        // The directories keep tracking the same files if the datasets agree on which
+        let filter = if all_data.iter().all(|d| d.filter == all_data[0].filter) { all_data[0].filter.clone() } else { TreeFilter::default() };
         md.extend(given_md);
 
         // Create the new data
         let data = Data {
             id: self.curr_id,
+            uid: new_uid(),
             md,
-            paths
+            paths,
+            hashes: vec![],
+            sizes: vec![],
+            sidecars,
+            filter,
+            trees: vec![],
+            stored: all_data.iter().all(|d| d.stored)
         };
 
         // Add the data to the database
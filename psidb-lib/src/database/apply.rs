@@ -0,0 +1,230 @@
+use std::fs;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use super::Database;
+use super::entry::{data::Data, connection::Connection, action::Action, metadata::{Metadata, MetaValue}};
+use super::entry::new_uid;
+use super::relocate::HashCache;
+
+// A transform being applied to data, from creating its output directory to registering its output
+pub(super) struct Run {
+    pub transform_id: u64,
+    pub data_ids: Vec<u64>,
+    pub in_data_hashes: Vec<String>,
+    pub id: u64, // The id of the new data, also the name of the output directory
+    pub out_dir: PathBuf,
+    pub out_dir_as_cwd: bool
+}
+
+impl Database {
+    fn check_apply_ids(&self, transform_id: u64, data_ids: &[u64]) -> Result<(), Box<dyn Error>> {
+        // Check if the transform exists
+        if self.get_transform(transform_id).is_none() {
+            println!("Error: Transform with id {} does not exist", transform_id);
+            return Err(format!("Transform with id {} does not eixst", transform_id).into());
+        }
+
+        // Check if the data ids exist
+        for id in data_ids {
+            if self.get_data(*id).is_none() {
+                println!("Error: Data with id {} does not exist", *id);
+                return Err(format!("Data with id {} does not eixst", *id).into());
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn data_hashes(&self, data_ids: &[u64]) -> Result<Vec<String>, Box<dyn Error>> {
+        // Files that did not change since they were last hashed are not read again
+        let cache_path = self.get_hash_cache_path();
+        let mut cache = HashCache::load(&cache_path)?;
+        let hashes = data_ids
+            .iter()
+            .map(|id| cache.hash_paths(&self.get_data(*id).unwrap().paths))
+            .collect::<Result<Vec<String>, _>>()?;
+        cache.save(&cache_path)?;
+        Ok(hashes)
+    }
+
+    fn find_cached_apply(&self, transform_id: u64, data_ids: &[u64], in_data_hashes: &[String]) -> Option<(u64, u64)> {
+        // The most recent Apply of the same transform to the same data with the same contents
+        self.out_connections(transform_id)
+            .into_iter()
+            .rev()
+            .filter(|c| c.action == Action::Apply && c.in_transform_ids == [transform_id] && c.in_data_ids == data_ids)
+            .find(|c| c.in_data_hashes == in_data_hashes && c.out_data_ids.len() == 1 && self.get_data(c.out_data_ids[0]).is_some())
+            .map(|c| (c.out_data_ids[0], c.id))
+    }
+
+    /// Returns the ids of the output data and of the connection of an earlier identical `apply`, if there is one
+    pub fn cached_apply(&self, transform_id: u64, data_ids: &[u64]) -> Result<Option<(u64, u64)>, Box<dyn Error>> {
+        self.check_apply_ids(transform_id, data_ids)?;
+        let in_data_hashes = self.data_hashes(data_ids)?;
+        Ok(self.find_cached_apply(transform_id, data_ids, &in_data_hashes))
+    }
+
+    fn quarantine_run(&self, out_dir: &Path, id: u64) -> Result<(), Box<dyn Error>> {
+        // Nothing to keep if the run did not write anything
+        if fs::read_dir(out_dir)?.next().is_none() {
+            fs::remove_dir(out_dir)?;
+            return Ok(());
+        }
+
+        let failed_dir = self.get_data_root().join("failed");
+        fs::DirBuilder::new().recursive(true).create(&failed_dir)?;
+        let mut quarantine_dir = failed_dir.join(id.to_string());
+        if quarantine_dir.exists() {
+            quarantine_dir = failed_dir.join(format!("{}-{}", id, Utc::now().format("%Y%m%dT%H%M%S%.f")));
+        }
+        fs::rename(out_dir, &quarantine_dir)?;
+        println!("Moved the outputs of the failed run to {}", quarantine_dir.to_str().unwrap());
+        Ok(())
+    }
+
+    pub(super) fn start_run(&self, transform_id: u64, data_ids: &[u64], in_data_hashes: Vec<String>, id: u64, out_dir_as_cwd: bool) -> Result<Run, Box<dyn Error>> {
+        // Create the output directory of this run
+        let out_dir = self.get_data_root().join(id.to_string());
+        if out_dir.exists() && fs::read_dir(&out_dir)?.next().is_some() {
+            println!("Error: The output directory {} already exists and is not empty", out_dir.to_str().unwrap());
+            return Err(format!("The output directory {} already exists and is not empty", out_dir.to_str().unwrap()).into());
+        }
+        fs::DirBuilder::new().recursive(true).create(&out_dir)?;
+        let out_dir = out_dir.canonicalize()?;
+
+        Ok(Run {
+            transform_id,
+            data_ids: data_ids.to_vec(),
+            in_data_hashes,
+            id,
+            out_dir,
+            out_dir_as_cwd
+        })
+    }
+
+    // Runs the scripts of a run, writing what they printed to log
+    pub(super) fn run(&self, run: &Run, log: &mut String) -> Result<Data, Box<dyn Error>> {
+        // Get the transform and the data
+        let transform = self.get_transform(run.transform_id).unwrap();
+        let data: Vec<&Data> = run.data_ids.iter().map(|id| self.get_data(*id).unwrap()).collect();
+
+        // Apply the scripts in the transform sequentially
+        let new_data = match transform.apply(&data, run.id, &run.out_dir, run.out_dir_as_cwd, log) {
+            Ok(new_data) => new_data,
+            Err(e) => {
+                self.quarantine_run(&run.out_dir, run.id)?;
+                return Err(e);
+            }
+        };
+
+        // Clean up the output directory if the scripts did not use it
+        if fs::read_dir(&run.out_dir)?.next().is_none() {
+            fs::remove_dir(&run.out_dir)?;
+        }
+        Ok(new_data)
+    }
+
+    // Adds the outputs of a run as data, with md (which inherited_md fills in) and connects them to its inputs
+    pub(super) fn finish_run(&mut self, run: Run, mut new_data: Data, md: Metadata, inherited_md: Metadata, force: bool, reserved_id: bool) -> Result<(u64, u64), Box<dyn Error>> {
+        // Add the metadata of the sidecars of the outputs, and the current time if it doesn't already exist
+        new_data.md = md.clone();
+        self.remove_sidecar_paths(&mut new_data);
+        self.read_sidecars(&mut new_data)?;
+        if !new_data.md.contains_key("time") {
+            new_data.md.insert("time".to_owned(), MetaValue::now());
+        }
+        for (k, v) in inherited_md {
+            new_data.md.entry(k).or_insert(v);
+        }
+
+        // The metadata is complete only now, so this is where it must follow the data template
+        if let Err(e) = self.apply_template(&mut new_data.md, self.settings.data_template.as_ref()) {
+            if run.out_dir.exists() {
+                self.quarantine_run(&run.out_dir, run.id)?;
+            }
+            return Err(e);
+        }
+
+        // Add the data to the database (a forced re-run may reproduce data that is already in the database)
+        let (new_data_id, inserted) = match self.find_data(&new_data) {
+            Some(id) if force => {
+                println!("The outputs are the same as the data with id {}", id);
+                self.get_data_mut(id).unwrap().hash()?;
+                (id, false)
+            }
+            _ => match self.try_insert_data(new_data, reserved_id.then_some(run.id)) {
+                Ok(id) => (id, true),
+                Err(e) => {
+                    if run.out_dir.exists() {
+                        self.quarantine_run(&run.out_dir, run.id)?;
+                    }
+                    return Err(e);
+                }
+            }
+        };
+
+        // Add the current time to the meta data if it doesn't already exist
+        let mut md = md;
+        if !md.contains_key("time") {
+            md.insert("time".to_owned(), MetaValue::now());
+        }
+
+        // Connect the new data to the transform
+        let connection = Connection {
+            id: self.curr_id,
+            uid: new_uid(),
+            md,
+            action: Action::Apply,
+            in_data_ids: run.data_ids,
+            out_data_ids: vec![new_data_id],
+            in_transform_ids: vec![run.transform_id],
+            out_transform_ids: vec![],
+            in_data_hashes: run.in_data_hashes
+        };
+
+        // A forced re-run that reproduced an existing connection only refreshes its input hashes
+        if let Some(id) = self.find_connection(&connection) {
+            if force {
+                self.get_connection_mut(id).unwrap().in_data_hashes = connection.in_data_hashes;
+                return Ok((new_data_id, id));
+            }
+        }
+        let new_connect_id = self.try_add_connection(connection)?;
+
+        // Outputs follow the store setting like any new data, so that the data root cannot be changed by accident. They
+        // only move once the connection is added.
+        if inserted && self.settings.store {
+            self.store_data(new_data_id)?;
+        }
+        Ok((new_data_id, new_connect_id))
+    }
+
+    /// Applies a transform to data and returns the ids of the new data and connection, and whether they came from the cache
+    /// (an earlier identical `apply`). Set force to run the scripts even when there is a cache hit.
+    pub fn apply(&mut self, transform_id: u64, data_ids: &[u64], meta_data_str: Option<&str>, out_dir_as_cwd: bool, force: bool) -> Result<(u64, u64, bool), Box<dyn Error>> {
+        let md = Self::parse_md(meta_data_str)?;
+        self.apply_with_md(transform_id, data_ids, md, out_dir_as_cwd, force)
+    }
+
+    pub(super) fn apply_with_md(&mut self, transform_id: u64, data_ids: &[u64], md: Metadata, out_dir_as_cwd: bool, force: bool) -> Result<(u64, u64, bool), Box<dyn Error>> {
+        self.check_apply_ids(transform_id, data_ids)?;
+
+        // Reuse the output of an identical computation
+        let in_data_hashes = self.data_hashes(data_ids)?;
+        if !force {
+            if let Some((data_id, connect_id)) = self.find_cached_apply(transform_id, data_ids, &in_data_hashes) {
+                println!("Cache hit: transform {} was already applied to data {:?} (connection with id {})", transform_id, data_ids, connect_id);
+                return Ok((data_id, connect_id, true));
+            }
+        }
+
+        let run = self.start_run(transform_id, data_ids, in_data_hashes, self.curr_id, out_dir_as_cwd)?;
+        let mut log = String::new();
+        let new_data = self.run(&run, &mut log);
+        print!("{}", log);
+        let new_data = new_data?;
+        let (new_data_id, new_connect_id) = self.finish_run(run, new_data, md, Metadata::new(), force, false)?;
+
+        Ok((new_data_id, new_connect_id, false))
+    }
+}
@@ -0,0 +1,53 @@
+use std::error::Error;
+use super::Database;
+use super::entry::metadata::Metadata;
+
+impl Database {
+    fn get_md_mut(&mut self, id: u64) -> Option<&mut Metadata> {
+        if self.get_data(id).is_some() {
+            self.get_data_mut(id).map(|d| &mut d.md)
+        } else if self.get_transform(id).is_some() {
+            self.get_transform_mut(id).map(|t| &mut t.md)
+        } else {
+            self.get_connection_mut(id).map(|c| &mut c.md)
+        }
+    }
+
+    /// Sets the metadata in meta_data_str (key=value;key2=value2) and removes the keys in unset_keys
+    pub fn set_md(&mut self, id: u64, meta_data_str: Option<&str>, unset_keys: &[String]) -> Result<(), Box<dyn Error>> {
+        let new_md = Self::parse_md(meta_data_str)?;
+        let Some(md) = self.get_md_mut(id) else {
+            println!("Error: Entry with id {} does not exist", id);
+            return Err(format!("Entry with id {} does not exist", id).into());
+        };
+        for key in unset_keys {
+            md.remove(key);
+        }
+        md.extend(new_md);
+        Ok(())
+    }
+
+    /// Removes an entry from the database (the files of a dataset are left alone). Data and transforms can only be
+    /// removed once no connection refers to them.
+    pub fn remove(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
+        if self.get_connection(id).is_some() {
+            self.remove_entries(&[id]);
+            return Ok(());
+        }
+        if self.get_data(id).is_none() && self.get_transform(id).is_none() {
+            println!("Error: Entry with id {} does not exist", id);
+            return Err(format!("Entry with id {} does not exist", id).into());
+        }
+
+        let mut connection_ids: Vec<u64> = self.out_connections(id).iter().chain(&self.in_connections(id)).map(|c| c.id).collect();
+        connection_ids.sort_unstable();
+        connection_ids.dedup();
+        let connection_ids: Vec<String> = connection_ids.iter().map(u64::to_string).collect();
+        if !connection_ids.is_empty() {
+            println!("Error: Entry {} is used by the connections {}, remove them first", id, connection_ids.join(", "));
+            return Err(format!("Entry {} is used by the connections {}", id, connection_ids.join(", ")).into());
+        }
+        self.remove_entries(&[id]);
+        Ok(())
+    }
+}
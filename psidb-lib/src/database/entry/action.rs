@@ -1,11 +1,12 @@
 use clap::ValueEnum;
-use strum_macros::EnumString;
+use strum_macros::{Display, EnumString};
 use serde::{Serialize, Deserialize};
 
-#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, ValueEnum)]
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, ValueEnum)]
 #[strum(serialize_all = "snake_case")]
 pub enum Action {
     Apply, // Applies a transform to a data entry
     Chain, // Chains multiple transforms together
-    Link // Links multiple data entries together
+    Link, // Links multiple data entries together
+    Update // Replaces data or a transform with a newer version
 }
\ No newline at end of file
@@ -1,17 +1,33 @@
-use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use super::action::Action;
-use super::Entry;
+use super::{Entry, new_uid};
+use super::metadata::Metadata;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Connection {
     pub id: u64,
-    pub md: HashMap<String, String>,
+    #[serde(default = "new_uid")]
+    pub uid: String,
+    pub md: Metadata,
     pub action: Action,
     pub in_data_ids: Vec<u64>,
     pub out_data_ids: Vec<u64>,
     pub in_transform_ids: Vec<u64>,
-    pub out_transform_ids: Vec<u64>
+    pub out_transform_ids: Vec<u64>,
+    #[serde(default)]
+    pub in_data_hashes: Vec<String> // The content hashes of the input data when an Apply connection was made
+}
+
+impl Connection {
+    /// The ids of the input data and transforms
+    pub fn in_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.in_data_ids.iter().chain(&self.in_transform_ids).copied()
+    }
+
+    /// The ids of the output data and transforms
+    pub fn out_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.out_data_ids.iter().chain(&self.out_transform_ids).copied()
+    }
 }
 
 impl std::cmp::PartialEq for Connection {
@@ -24,8 +40,20 @@ impl std::cmp::PartialEq for Connection {
     }
 }
 
+impl std::fmt::Display for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let in_ids: Vec<String> = self.in_ids().map(|id| id.to_string()).collect();
+        let out_ids: Vec<String> = self.out_ids().map(|id| id.to_string()).collect();
+        write!(f, "connection {}: {} {} -> {}", self.id, self.action, in_ids.join(", "), out_ids.join(", "))
+    }
+}
+
 impl Entry for Connection {
     fn get_id(&self) -> u64 {
         self.id
     }
+
+    fn get_uid(&self) -> &str {
+        &self.uid
+    }
 }
\ No newline at end of file
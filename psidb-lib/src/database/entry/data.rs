@@ -1,18 +1,127 @@
-use std::collections::HashMap;
+use std::path::Path;
 use serde::{Serialize, Deserialize};
 use crate::utils;
-use super::Entry;
+use super::{Entry, new_uid};
+use super::metadata::Metadata;
+use super::tree::{Tree, TreeFilter, FileChange};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Data {
     pub id: u64,
-    pub md: HashMap<String, String>,
-    pub paths: Vec<String>
+    #[serde(default = "new_uid")]
+    pub uid: String,
+    pub md: Metadata,
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub hashes: Vec<String>, // The content hash of each path when the data was registered
+    #[serde(default)]
+    pub sizes: Vec<u64>, // The size of each path then (the tracked files of directories)
+    #[serde(default)]
+    pub sidecars: Vec<Sidecar>, // The files next to the paths that metadata was read from
+    #[serde(default)]
+    pub filter: TreeFilter, // The files of the directories that are tracked
+    #[serde(default)]
+    pub trees: Vec<Tree>, // The manifest of each directory when the data was hashed
+    #[serde(default)]
+    pub stored: bool // Whether the files were moved into the object store (see `store.rs`)
+}
+
+/// A metadata file next to a data file (e.g. `foo.dat.json` next to `foo.dat`)
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Sidecar {
+    pub path: String,
+    pub hash: String, // The content hash of the sidecar when its metadata was read
+    pub keys: Vec<String> // The metadata keys that came from the sidecar
+}
+
+impl Data {
+    pub fn hash(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut hashes = Vec::with_capacity(self.paths.len());
+        let mut sizes = Vec::with_capacity(self.paths.len());
+        let mut trees = vec![];
+        for path in &self.paths {
+            if !Path::new(path).exists() {
+                println!("Error: Path {} does not exist", path);
+                return Err(format!("Path {} does not exist", path).into());
+            }
+            if Path::new(path).is_dir() {
+                // Directories hash the files they track
+                let tree = Tree::scan(path, &self.filter)?;
+                hashes.push(tree.digest());
+                sizes.push(tree.files.iter().map(|f| f.size).sum());
+                trees.push(tree);
+            } else {
+                hashes.push(utils::hash_path(Path::new(path))?);
+                sizes.push(std::fs::metadata(path)?.len());
+            }
+        }
+        self.hashes = hashes;
+        self.sizes = sizes;
+        self.trees = trees;
+        Ok(())
+    }
+
+    // The current hash of one of the paths
+    fn current_hash(&self, path: &str) -> Result<String, Box<dyn std::error::Error>> {
+        if Path::new(path).is_dir() {
+            Ok(Tree::scan(path, &self.filter)?.digest())
+        } else {
+            utils::hash_path(Path::new(path))
+        }
+    }
+
+    /// The paths that are missing or whose contents changed since they were hashed (unknown for data without hashes)
+    pub fn changed_paths(&self) -> Vec<String> {
+        self.paths
+            .iter()
+            .zip(&self.hashes)
+            .filter(|(path, hash)| self.current_hash(path).map_or(true, |h| &h != *hash))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// The files added, removed or modified inside the given directories of the data since they were hashed
+    pub fn changed_files(&self, dirs: &[String]) -> Vec<(String, FileChange)> {
+        let mut changes = vec![];
+        for tree in self.trees.iter().filter(|t| dirs.contains(&t.root)) {
+            let current = Tree::scan(&tree.root, &self.filter).unwrap_or_else(|_| Tree { root: tree.root.clone(), files: vec![] });
+            for (file, change) in tree.diff(&current) {
+                changes.push((Path::new(&tree.root).join(file).to_str().unwrap().to_owned(), change));
+            }
+        }
+        changes
+    }
+
+    /// The directory of the data that tracks the given (absolute) path, and the path relative to it
+    pub fn tracking_dir<'a>(&self, path: &'a Path) -> Option<(&str, &'a Path)> {
+        self.paths.iter().find_map(|dir| {
+            let relative = path.strip_prefix(dir).ok().filter(|r| !r.as_os_str().is_empty())?;
+            match self.trees.iter().find(|t| t.root == *dir) {
+                Some(tree) if !tree.contains(relative) => None,
+                _ => Some((dir.as_str(), relative))
+            }
+        })
+    }
+
+    /// The sidecars that are missing or whose contents changed since their metadata was read
+    pub fn changed_sidecars(&self) -> Vec<String> {
+        self.sidecars
+            .iter()
+            .filter(|sidecar| utils::hash_path(Path::new(&sidecar.path)).map_or(true, |h| h != sidecar.hash))
+            .map(|sidecar| sidecar.path.clone())
+            .collect()
+    }
 }
 
 impl std::cmp::PartialEq for Data {
     fn eq(&self, rhs: &Data) -> bool {
-        utils::is_permutation_small(&self.paths, &rhs.paths)
+        utils::is_permutation(&self.paths, &rhs.paths)
+    }
+}
+
+impl std::fmt::Display for Data {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "data {}: {}", self.id, self.paths.join(", "))
     }
 }
 
@@ -20,4 +129,8 @@ impl Entry for Data {
     fn get_id(&self) -> u64 {
         self.id
     }
+
+    fn get_uid(&self) -> &str {
+        &self.uid
+    }
 }
\ No newline at end of file
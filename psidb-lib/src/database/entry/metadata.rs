@@ -0,0 +1,241 @@
+pub mod parser;
+use std::fmt;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::collections::{HashMap, BTreeMap};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::ser::SerializeMap;
+use serde::de::{self, Visitor, SeqAccess, MapAccess};
+use chrono::{DateTime, FixedOffset, Utc, SecondsFormat};
+
+pub type Metadata = HashMap<String, MetaValue>;
+
+/// A metadata value. It is stored as the matching RON/JSON value, with dates as `{"$datetime": "<RFC 3339 date>"}` so
+/// that they cannot be mistaken for strings (and strings never change type).
+#[derive(Clone, PartialEq, Debug)]
+pub enum MetaValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    DateTime(DateTime<FixedOffset>),
+    List(Vec<MetaValue>),
+    Map(BTreeMap<String, MetaValue>)
+}
+
+// The key of the map a date is stored as
+pub const DATETIME_TAG: &str = "$datetime";
+
+// The names accepted in `key:type=value`
+pub const TYPE_NAMES: [&str; 6] = ["str", "int", "float", "bool", "datetime", "list"];
+
+impl MetaValue {
+    /// The current time, e.g. for the `time` key
+    pub fn now() -> MetaValue {
+        MetaValue::DateTime(Utc::now().into())
+    }
+
+    /// The value a string most likely stands for: a bool, an integer, a float, an RFC 3339 date, or the string itself
+    pub fn infer(s: &str) -> MetaValue {
+        if let Ok(b) = s.parse::<bool>() {
+            MetaValue::Bool(b)
+        } else if let Ok(i) = s.parse::<i64>() {
+            MetaValue::Int(i)
+        } else if let Some(f) = s.parse::<f64>().ok().filter(|f| f.is_finite()) {
+            MetaValue::Float(f)
+        } else if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            MetaValue::DateTime(time)
+        } else {
+            MetaValue::String(s.to_owned())
+        }
+    }
+
+    /// Parses a string as the given type (one of `TYPE_NAMES`), lists are comma separated
+    pub fn parse_as(s: &str, type_name: &str) -> Result<MetaValue, Box<dyn Error>> {
+        if !TYPE_NAMES.contains(&type_name) {
+            println!("Error: Unknown metadata type {}, expected one of {}", type_name, TYPE_NAMES.join(", "));
+            return Err(format!("Unknown metadata type {}, expected one of {}", type_name, TYPE_NAMES.join(", ")).into());
+        }
+        Self::try_parse_as(s, type_name).ok_or_else(|| {
+            println!("Error: {} is not a valid {}", s, type_name);
+            format!("{} is not a valid {}", s, type_name).into()
+        })
+    }
+
+    /// Parses a string as the given type, None if it is not a valid value of that type
+    pub fn try_parse_as(s: &str, type_name: &str) -> Option<MetaValue> {
+        match type_name {
+            "str" => Some(MetaValue::String(s.to_owned())),
+            "int" => s.parse().ok().map(MetaValue::Int),
+            "float" => s.parse().ok().map(MetaValue::Float),
+            "bool" => s.parse().ok().map(MetaValue::Bool),
+            "datetime" => DateTime::parse_from_rfc3339(s).ok().map(MetaValue::DateTime),
+            "list" if s.is_empty() => Some(MetaValue::List(vec![])),
+            "list" => Some(MetaValue::List(s.split(',').map(|item| MetaValue::infer(item.trim())).collect())),
+            _ => None
+        }
+    }
+
+    /// The name of the type of the value (as in `key:type=value`)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            MetaValue::String(..) => "str",
+            MetaValue::Int(..) => "int",
+            MetaValue::Float(..) => "float",
+            MetaValue::Bool(..) => "bool",
+            MetaValue::DateTime(..) => "datetime",
+            MetaValue::List(..) => "list",
+            MetaValue::Map(..) => "map"
+        }
+    }
+
+    /// Compares values of compatible types (integers and floats compare as numbers), None for other types
+    pub fn compare(&self, other: &MetaValue) -> Option<Ordering> {
+        match (self, other) {
+            (MetaValue::String(a), MetaValue::String(b)) => Some(a.cmp(b)),
+            (MetaValue::Int(a), MetaValue::Int(b)) => Some(a.cmp(b)),
+            (MetaValue::Int(a), MetaValue::Float(b)) => (*a as f64).partial_cmp(b),
+            (MetaValue::Float(a), MetaValue::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (MetaValue::Float(a), MetaValue::Float(b)) => a.partial_cmp(b),
+            (MetaValue::Bool(a), MetaValue::Bool(b)) => Some(a.cmp(b)),
+            (MetaValue::DateTime(a), MetaValue::DateTime(b)) => Some(a.cmp(b)),
+            (MetaValue::List(a), MetaValue::List(b)) => {
+                for (x, y) in a.iter().zip(b) {
+                    match x.compare(y)? {
+                        Ordering::Equal => continue,
+                        ordering => return Some(ordering)
+                    }
+                }
+                Some(a.len().cmp(&b.len()))
+            }
+            (MetaValue::Map(a), MetaValue::Map(b)) if a == b => Some(Ordering::Equal),
+            _ => None
+        }
+    }
+
+    /// A total order for sorting: numbers, then dates, bools, strings, lists and maps
+    pub fn sort_cmp(&self, other: &MetaValue) -> Ordering {
+        let rank = |v: &MetaValue| match v {
+            MetaValue::Int(..) | MetaValue::Float(..) => 0,
+            MetaValue::DateTime(..) => 1,
+            MetaValue::Bool(..) => 2,
+            MetaValue::String(..) => 3,
+            MetaValue::List(..) => 4,
+            MetaValue::Map(..) => 5
+        };
+        let as_f64 = |v: &MetaValue| match v {
+            MetaValue::Int(i) => *i as f64,
+            MetaValue::Float(x) => *x,
+            _ => 0.0
+        };
+        rank(self).cmp(&rank(other)).then_with(|| match (self, other) {
+            (MetaValue::DateTime(a), MetaValue::DateTime(b)) => a.cmp(b),
+            (MetaValue::Bool(a), MetaValue::Bool(b)) => a.cmp(b),
+            (MetaValue::String(a), MetaValue::String(b)) => a.cmp(b),
+            (MetaValue::List(..), _) | (MetaValue::Map(..), _) => self.to_string().cmp(&other.to_string()),
+            _ => as_f64(self).total_cmp(&as_f64(other))
+        })
+    }
+}
+
+impl fmt::Display for MetaValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetaValue::String(s) => write!(f, "{}", s),
+            MetaValue::Int(i) => write!(f, "{}", i),
+            MetaValue::Float(x) => write!(f, "{:?}", x), // Keeps the decimal point of round numbers (300.0)
+            MetaValue::Bool(b) => write!(f, "{}", b),
+            MetaValue::DateTime(time) => write!(f, "{}", time.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            MetaValue::List(items) => {
+                let items: Vec<String> = items.iter().map(MetaValue::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            MetaValue::Map(map) => {
+                let items: Vec<String> = map.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+        }
+    }
+}
+
+impl From<&str> for MetaValue {
+    fn from(s: &str) -> MetaValue {
+        MetaValue::String(s.to_owned())
+    }
+}
+
+impl Serialize for MetaValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MetaValue::String(s) => serializer.serialize_str(s),
+            MetaValue::Int(i) => serializer.serialize_i64(*i),
+            MetaValue::Float(x) => serializer.serialize_f64(*x),
+            MetaValue::Bool(b) => serializer.serialize_bool(*b),
+            MetaValue::DateTime(time) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(DATETIME_TAG, &time.to_rfc3339_opts(SecondsFormat::AutoSi, true))?;
+                map.end()
+            }
+            MetaValue::List(items) => items.serialize(serializer),
+            MetaValue::Map(map) => map.serialize(serializer)
+        }
+    }
+}
+
+struct MetaValueVisitor;
+
+impl<'de> Visitor<'de> for MetaValueVisitor {
+    type Value = MetaValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string, number, bool, list or map")
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<MetaValue, E> {
+        Ok(MetaValue::Bool(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, i: i64) -> Result<MetaValue, E> {
+        Ok(MetaValue::Int(i))
+    }
+
+    fn visit_u64<E: de::Error>(self, u: u64) -> Result<MetaValue, E> {
+        Ok(i64::try_from(u).map_or(MetaValue::Float(u as f64), MetaValue::Int))
+    }
+
+    fn visit_f64<E: de::Error>(self, x: f64) -> Result<MetaValue, E> {
+        Ok(MetaValue::Float(x))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<MetaValue, E> {
+        Ok(MetaValue::String(s.to_owned()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<MetaValue, A::Error> {
+        let mut items = vec![];
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(MetaValue::List(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<MetaValue, A::Error> {
+        let mut map = BTreeMap::new();
+        while let Some((k, v)) = access.next_entry()? {
+            map.insert(k, v);
+        }
+        // Dates are stored as a map with only the tag
+        match map.get(DATETIME_TAG) {
+            Some(MetaValue::String(s)) if map.len() == 1 => DateTime::parse_from_rfc3339(s)
+                .map(MetaValue::DateTime)
+                .map_err(|e| de::Error::custom(format!("{} is not an RFC 3339 date: {}", s, e))),
+            _ => Ok(MetaValue::Map(map))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MetaValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MetaValue, D::Error> {
+        deserializer.deserialize_any(MetaValueVisitor)
+    }
+}
@@ -0,0 +1,387 @@
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::collections::HashSet;
+use chrono::DateTime;
+use super::{Metadata, MetaValue, TYPE_NAMES};
+
+// Characters that end an unquoted key
+const KEY_END: [char; 2] = ['=', ';'];
+
+/// Parses metadata written as `key=value;key2=value2`.
+///
+/// - Whitespace around keys and values is ignored, empty entries (e.g. a trailing `;`) are skipped
+/// - Values can be quoted with `"` or `'` to keep whitespace and `;`, and quoted values are always strings
+/// - A backslash escapes the next character (`\;`, `\"`, `\\`), `\n` and `\t` are a newline and a tab
+/// - `key:type=value` gives the type of the value (one of `TYPE_NAMES`), otherwise it is inferred
+/// - `@path` reads the metadata of a JSON, TOML or YAML file, the other entries override its keys
+pub fn parse(s: &str) -> Result<Metadata, Box<dyn Error>> {
+    Parser { chars: s.chars().peekable(), pos: 0 }.parse().map_err(|e| {
+        println!("Error: Invalid metadata {:?}: {}", s, e);
+        format!("Invalid metadata {:?}: {}", s, e).into()
+    })
+}
+
+/// Reads metadata from a JSON, TOML or YAML file (chosen by its extension) holding a map of keys to values. Dates
+/// are TOML dates or `{"$datetime": "<RFC 3339 date>"}`, strings stay strings.
+pub fn read_file(path: &str) -> Result<Metadata, Box<dyn Error>> {
+    load_file(path).map_err(|e| {
+        println!("Error: {}", e);
+        e.into()
+    })
+}
+
+fn load_file(path: &str) -> Result<Metadata, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Could not read metadata file {}: {}", path, e))?;
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    let md = match extension {
+        "json" => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+        "toml" => toml::from_str::<toml::Table>(&contents)
+            .map(|table| table.into_iter().map(|(k, v)| (k, from_toml(v))).collect())
+            .map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        _ => Err("expected a .json, .toml, .yaml or .yml file".to_owned())
+    };
+    md.map_err(|e| format!("Could not parse metadata file {}: {}", path, e))
+}
+
+/// Writes a string (e.g. a key or a file path) so that `parse` reads it back unchanged
+pub fn quote(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.contains([';', '=', '"', '\'', '\\', '@', ':', '\n', '\t']);
+    if plain {
+        return s.to_owned();
+    }
+    quoted(s)
+}
+
+fn quoted(s: &str) -> String {
+    let escaped = s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+// Writes a key unquoted, with backslash escapes, so that a type can follow it
+fn escape_key(key: &str) -> String {
+    let mut escaped = String::new();
+    for c in key.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            ';' | '=' | '"' | '\'' | '\\' | '@' | ':' | ' ' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Writes a metadata entry as `key=value` so that `parse` reads it back with the same value and type, adding the
+/// type (`key:type=value`) where it would not be inferred. None for maps and lists of items that do not read back.
+pub fn format_entry(key: &str, value: &MetaValue) -> Option<String> {
+    let text = match value {
+        MetaValue::List(items) => items.iter().map(MetaValue::to_string).collect::<Vec<String>>().join(","),
+        _ => value.to_string()
+    };
+    // NaN is the only value that is not equal to itself
+    let same = |parsed: MetaValue| parsed == *value || matches!((&parsed, value), (MetaValue::Float(a), MetaValue::Float(b)) if a.is_nan() && b.is_nan());
+    if !MetaValue::try_parse_as(&text, value.type_name()).is_some_and(same) {
+        return None;
+    }
+    // A key with a colon would be read as a typed key, so its type is always given
+    let untyped = match value {
+        _ if key.contains(':') => None,
+        MetaValue::String(s) if MetaValue::infer(s) == *value => Some(quote(s)),
+        MetaValue::String(s) => Some(quoted(s)),
+        MetaValue::List(..) => None,
+        // Numbers, bools and dates have no characters that need quoting
+        _ if MetaValue::infer(&text) == *value => Some(text.clone()),
+        _ => None
+    };
+    Some(match untyped {
+        Some(untyped) => format!("{}={}", escape_key(key), untyped),
+        None => format!("{}:{}={}", escape_key(key), value.type_name(), quote(&text))
+    })
+}
+
+// TOML has its own date type, the other values map directly
+fn from_toml(value: toml::Value) -> MetaValue {
+    match value {
+        toml::Value::String(s) => MetaValue::String(s),
+        toml::Value::Integer(i) => MetaValue::Int(i),
+        toml::Value::Float(x) => MetaValue::Float(x),
+        toml::Value::Boolean(b) => MetaValue::Bool(b),
+        toml::Value::Datetime(time) => DateTime::parse_from_rfc3339(&time.to_string())
+            .map_or_else(|_| MetaValue::String(time.to_string()), MetaValue::DateTime),
+        toml::Value::Array(items) => MetaValue::List(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => MetaValue::Map(table.into_iter().map(|(k, v)| (k, from_toml(v))).collect())
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    pos: usize // Position of the next character, for error messages
+}
+
+// A key or value, and whether it was quoted
+struct Token {
+    text: String,
+    quoted: bool
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<char> {
+        self.pos += 1;
+        self.chars.next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {
+            self.pos += 1;
+        }
+    }
+
+    fn parse(&mut self) -> Result<Metadata, String> {
+        let mut md = Metadata::new();
+        let mut included = Metadata::new();
+        let mut keys = HashSet::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                None => break,
+                Some(';') => {
+                    self.next();
+                    continue;
+                }
+                Some('@') => {
+                    self.next();
+                    let path = self.token(&[';'])?;
+                    included.extend(load_file(&path.text)?);
+                    self.end_of_entry()?;
+                    continue;
+                }
+                _ => ()
+            }
+
+            let start = self.pos;
+            let key = self.token(&KEY_END)?;
+            if self.next() != Some('=') {
+                return Err(format!("expected key=value at character {}", start + 1));
+            }
+            let value = self.token(&[';'])?;
+            self.end_of_entry()?;
+
+            let (key, type_name) = match key.text.rsplit_once(':') {
+                Some((name, type_name)) if !key.quoted && TYPE_NAMES.contains(&type_name) => (name.to_owned(), Some(type_name.to_owned())),
+                _ => (key.text, None)
+            };
+            if key.is_empty() {
+                return Err(format!("empty key at character {}", start + 1));
+            }
+            if !keys.insert(key.clone()) {
+                return Err(format!("the key {} is given twice", key));
+            }
+            let value = match type_name {
+                Some(type_name) => MetaValue::try_parse_as(&value.text, &type_name)
+                    .ok_or_else(|| format!("{} is not a valid {} for {}", value.text, type_name, key))?,
+                None if value.quoted => MetaValue::String(value.text),
+                None => MetaValue::infer(&value.text)
+            };
+            md.insert(key, value);
+        }
+
+        // The entries given directly win over the files
+        included.extend(md);
+        Ok(included)
+    }
+
+    // Reads a quoted or unquoted key or value, stopping before one of the end characters
+    fn token(&mut self, end: &[char]) -> Result<Token, String> {
+        self.skip_whitespace();
+        if let Some(quote) = self.chars.next_if(|c| *c == '"' || *c == '\'') {
+            let start = self.pos;
+            self.pos += 1;
+            let mut text = String::new();
+            loop {
+                match self.next() {
+                    Some('\\') => text.push(self.escaped()?),
+                    Some(c) if c == quote => break,
+                    Some(c) => text.push(c),
+                    None => return Err(format!("unterminated quote at character {}", start + 1))
+                }
+            }
+            return Ok(Token { text, quoted: true });
+        }
+
+        // Trailing whitespace is dropped unless it is escaped
+        let mut text = String::new();
+        let mut kept_len = 0;
+        while let Some(c) = self.chars.next_if(|c| !end.contains(c)) {
+            self.pos += 1;
+            if c == '\\' {
+                text.push(self.escaped()?);
+                kept_len = text.len();
+            } else {
+                text.push(c);
+                if !c.is_whitespace() {
+                    kept_len = text.len();
+                }
+            }
+        }
+        text.truncate(kept_len);
+        Ok(Token { text, quoted: false })
+    }
+
+    fn escaped(&mut self) -> Result<char, String> {
+        match self.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some(c) => Ok(c),
+            None => Err("backslash at the end".to_owned())
+        }
+    }
+
+    fn end_of_entry(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            None | Some(';') => Ok(()),
+            Some(c) => Err(format!("unexpected {:?} at character {}, quote values that contain it", c, self.pos))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> MetaValue {
+        MetaValue::String(s.to_owned())
+    }
+
+    #[test]
+    fn infers_types() {
+        let md = parse("n=5; x=2.5; ok=true; when=2024-03-01T00:00:00Z; label=raw").unwrap();
+        assert_eq!(md["n"], MetaValue::Int(5));
+        assert_eq!(md["x"], MetaValue::Float(2.5));
+        assert_eq!(md["ok"], MetaValue::Bool(true));
+        assert!(matches!(md["when"], MetaValue::DateTime(_)));
+        assert_eq!(md["label"], string("raw"));
+    }
+
+    #[test]
+    fn skips_whitespace_and_empty_entries() {
+        let md = parse("  a = 1 ;; b=two words ; ").unwrap();
+        assert_eq!(md.len(), 2);
+        assert_eq!(md["a"], MetaValue::Int(1));
+        assert_eq!(md["b"], string("two words"));
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quoted_values_are_strings() {
+        let md = parse(r#"n="5"; s='a;b = c'; pad=" x ""#).unwrap();
+        assert_eq!(md["n"], string("5"));
+        assert_eq!(md["s"], string("a;b = c"));
+        assert_eq!(md["pad"], string(" x "));
+    }
+
+    #[test]
+    fn escapes() {
+        let md = parse(r#"a=x\;y; b="say \"hi\""; c=line\nbreak; d=tab\t; e=back\\slash; f=\ kept\ "#).unwrap();
+        assert_eq!(md["a"], string("x;y"));
+        assert_eq!(md["b"], string("say \"hi\""));
+        assert_eq!(md["c"], string("line\nbreak"));
+        assert_eq!(md["d"], string("tab\t"));
+        assert_eq!(md["e"], string("back\\slash"));
+        assert_eq!(md["f"], string(" kept "));
+    }
+
+    #[test]
+    fn typed_keys() {
+        let md = parse("n:str=5; x:float=3; l:list=1, a,2.5; e:list=; when:datetime=2024-03-01T00:00:00+01:00").unwrap();
+        assert_eq!(md["n"], string("5"));
+        assert_eq!(md["x"], MetaValue::Float(3.0));
+        assert_eq!(md["l"], MetaValue::List(vec![MetaValue::Int(1), string("a"), MetaValue::Float(2.5)]));
+        assert_eq!(md["e"], MetaValue::List(vec![]));
+        assert!(matches!(md["when"], MetaValue::DateTime(_)));
+
+        // Only known types split the key, and quoted keys are never split
+        let md = parse(r#"url:port=80; "n:int"=5"#).unwrap();
+        assert_eq!(md["url:port"], MetaValue::Int(80));
+        assert_eq!(md["n:int"], MetaValue::Int(5));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("novalue").is_err());
+        assert!(parse("a=1;=2").is_err());
+        assert!(parse(":int=2").is_err());
+        assert!(parse("a=1;a=2").is_err());
+        assert!(parse("a:int=1;a=2").is_err());
+        assert!(parse(r#"a="unterminated"#).is_err());
+        assert!(parse(r#"a="x" y"#).is_err());
+        assert!(parse(r"a=x\").is_err());
+        assert!(parse("n:int=five").is_err());
+        assert!(parse("t:datetime=yesterday").is_err());
+        assert!(parse("@/nonexistent/psidb-md.json").is_err());
+        assert!(parse("@md.txt").is_err());
+    }
+
+    #[test]
+    fn includes_files() {
+        let dir = std::env::temp_dir().join(format!("psidb-test-{}-parser", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let json = dir.join("md file.json");
+        fs::write(&json, r#"{"a": 1, "b": "from file", "when": {"$datetime": "2024-03-01T00:00:00Z"}, "s": "2024-03-01T00:00:00Z"}"#).unwrap();
+        let toml = dir.join("md.toml");
+        fs::write(&toml, "c = 2024-03-01T00:00:00Z\nd = [1, 2]\n").unwrap();
+        let yaml = dir.join("md.yaml");
+        fs::write(&yaml, "e: {f: true}\n").unwrap();
+
+        let md = parse(&format!("@{}; b=given; @{}; @{}", quote(json.to_str().unwrap()), quote(toml.to_str().unwrap()), quote(yaml.to_str().unwrap()))).unwrap();
+        assert_eq!(md["a"], MetaValue::Int(1));
+        // The entries given directly win over the files
+        assert_eq!(md["b"], string("given"));
+        assert!(matches!(md["when"], MetaValue::DateTime(_)));
+        assert_eq!(md["s"], string("2024-03-01T00:00:00Z"));
+        assert!(matches!(md["c"], MetaValue::DateTime(_)));
+        assert_eq!(md["d"], MetaValue::List(vec![MetaValue::Int(1), MetaValue::Int(2)]));
+        assert_eq!(md["e"], MetaValue::Map([("f".to_owned(), MetaValue::Bool(true))].into()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        // Keys (and the paths of includes) are read back unchanged, values are typed (see `format_entry`)
+        for s in ["plain", "two words", " padded ", "a;b", "k=v", "it's", "say \"hi\"", "back\\slash", "@file", "a:int", "line\nbreak", "tab\t", "5"] {
+            assert!(parse(&format!("{}=1", quote(s))).unwrap().contains_key(s), "{:?}", s);
+        }
+        assert_eq!(parse(&format!("k={}", quote("a;b"))).unwrap()["k"], string("a;b"));
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote(" padded "), "\" padded \"");
+    }
+
+    #[test]
+    fn format_entry_round_trips() {
+        let values = [
+            string("raw"), string("5"), string("true"), string(""), string("a;b"), string("2024-03-01T00:00:00Z"),
+            MetaValue::Int(-3), MetaValue::Float(300.0), MetaValue::Float(f64::NAN), MetaValue::Bool(false),
+            MetaValue::DateTime(DateTime::parse_from_rfc3339("2024-03-01T00:00:00+01:00").unwrap()),
+            MetaValue::List(vec![MetaValue::Int(1), string("a")]), MetaValue::List(vec![])
+        ];
+        for key in ["k", "a:int", "two words", "k=v"] {
+            for value in &values {
+                let entry = format_entry(key, value).unwrap();
+                let parsed = &parse(&entry).unwrap()[key];
+                // NaN is not equal to itself
+                assert!(parsed == value || parsed.to_string() == value.to_string() && parsed.type_name() == value.type_name(), "{}", entry);
+            }
+        }
+        assert_eq!(format_entry("k", &MetaValue::List(vec![string("a,b")])), None);
+        assert_eq!(format_entry("k", &MetaValue::Map(Default::default())), None);
+    }
+}
@@ -1,23 +1,75 @@
+use std::fmt::Write;
 use std::process::Command;
 use std::collections::HashMap;
-use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize, Deserializer};
 use itertools::izip;
 use regex;
-use super::Entry;
+use super::{Entry, new_uid};
+use super::metadata::Metadata;
 use super::data::Data;
+use super::tree::TreeFilter;
 use crate::utils;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Transform {
     pub id: u64,
-    pub md: HashMap<String, String>,
+    #[serde(default = "new_uid")]
+    pub uid: String,
+    pub md: Metadata,
     pub script_paths: Vec<String>,
-    pub script_args: Vec<Option<String>>,
+    #[serde(deserialize_with = "deserialize_script_args")]
+    pub script_args: Vec<Vec<String>>,
     pub script_git_hashes: Vec<Option<String>>
 }
 
+static GIT_LOCK: Mutex<()> = Mutex::new(());
+
+// Older databases stored the arguments of each script as a single optional string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredScriptArgs {
+    Words(Vec<String>),
+    Legacy(Option<String>)
+}
+
+fn deserialize_script_args<'de, D>(deserializer: D) -> Result<Vec<Vec<String>>, D::Error>
+where D: Deserializer<'de> {
+    let stored: Vec<StoredScriptArgs> = Deserialize::deserialize(deserializer)?;
+    Ok(stored
+        .into_iter()
+        .map(|args| match args {
+            StoredScriptArgs::Words(words) => words,
+            StoredScriptArgs::Legacy(None) => vec![],
+            // Keep the old string as a single argument if it cannot be split (e.g. unbalanced quotes)
+            StoredScriptArgs::Legacy(Some(s)) => shell_words::split(&s).unwrap_or_else(|_| vec![s])
+        })
+        .collect())
+}
+
 impl Transform {
-    pub fn apply(&self, data: &[&Data], id: u64) -> Result<Data, Box<dyn std::error::Error>> {
+    /// Splits one argument string per script into shell words (e.g. `--out "my file"` -> `["--out", "my file"]`)
+    pub fn split_args<T: AsRef<str>>(args_strs: &[T]) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+        let mut script_args = Vec::with_capacity(args_strs.len());
+        for args_str in args_strs {
+            match shell_words::split(args_str.as_ref()) {
+                Ok(words) => script_args.push(words),
+                Err(e) => {
+                    println!("Error: Could not parse the arguments `{}`: {}", args_str.as_ref(), e);
+                    return Err(format!("Could not parse the arguments `{}`: {}", args_str.as_ref(), e).into());
+                }
+            }
+        }
+        Ok(script_args)
+    }
+
+    /// Runs the scripts sequentially on data. Each script gets out_dir through `{psidb::out_dir}` and the
+    /// `PSIDB_OUT_DIR` environment variable (and as its working directory if out_dir_as_cwd is set). A script
+    /// that prints no `psidb::out_path` outputs every file it created or modified in out_dir. What the scripts
+    /// printed goes to log.
+    pub fn apply(&self, data: &[&Data], id: u64, out_dir: &Path, out_dir_as_cwd: bool, log: &mut String) -> Result<Data, Box<dyn std::error::Error>> {
         // Parser for the script otuput
         let re = regex::bytes::Regex::new(r"psidb::out_path (.*)")?;
 
@@ -35,6 +87,9 @@ impl Transform {
             let mut repo = git2::Repository::discover(script_dir);
             let using_git = repo.is_ok() && hash_str.is_some();
 
+            // Runs happening in parallel must not move HEAD under each other
+            let _git_guard = if using_git { Some(GIT_LOCK.lock().unwrap_or_else(|e| e.into_inner())) } else { None };
+
             // Checkout the hash if we have one
             let checkout_res = if using_git {
                 let hash_oid = git2::Oid::from_str(hash_str.as_ref().unwrap())?;
@@ -44,24 +99,43 @@ impl Transform {
             };
 
             // Make sure the script exists and is a file
-            utils::verify_file_path(&path)?;
+            utils::verify_file_path(path)?;
 
             // Construct the args to pass to the script
             let mut passed_args: Vec<String> = data_paths.clone(); // data_paths.iter().map(Asref::as_ref).collect();
-            let derefed_args = args.as_ref().map(AsRef::as_ref).unwrap_or_default();
-            let parsed_args = utils::parse_args(derefed_args, id);
-            passed_args.push(parsed_args);
+            passed_args.extend(args.iter().map(|arg| utils::parse_args(arg, id, out_dir)));
+
+            // Remember what was in the output directory so that we can tell what the script wrote
+            let files_before = Self::snapshot_dir(out_dir)?;
 
             // Run the script with the args provided
-            let output = Command::new(path).args(&passed_args).output()?;
+            let mut command = Command::new(path);
+            command.args(&passed_args).env("PSIDB_OUT_DIR", out_dir);
+            if out_dir_as_cwd {
+                command.current_dir(out_dir);
+            }
+            let output = command.output()?;
             if !output.status.success() {
-                println!("Error: {} {} failed\nOutput:\n{:#?}", path, passed_args.join(" "), output);
+                writeln!(log, "Error: {} {} failed\nOutput:\n{:#?}", path, passed_args.join(" "), output)?;
                 return Err(format!("{} {} failed\nOutput:\n{:?}", path, passed_args.join(" "), output).into());
             } 
-            println!("Ran: {} {}\nOutput:\n{:#?}", path, passed_args.join(" "), output);
+            writeln!(log, "Ran: {} {}\nOutput:\n{:#?}", path, passed_args.join(" "), output)?;
 
-            // Grab the output paths from the script to update data_paths
-            data_paths = re.captures_iter(&output.stdout).map(|c| String::from_utf8(c[1].to_vec()).unwrap()).collect();
+            // Grab the output paths from the script to update data_paths (relative paths are relative to the script's working directory)
+            data_paths = re.captures_iter(&output.stdout)
+                .map(|c| String::from_utf8(c[1].to_vec()).unwrap())
+                .map(|p| if out_dir_as_cwd { out_dir.join(p).to_str().unwrap().to_owned() } else { p })
+                .collect();
+
+            // Without any psidb::out_path, the outputs are the files the script left in the output directory
+            if data_paths.is_empty() {
+                data_paths = Self::snapshot_dir(out_dir)?
+                    .into_iter()
+                    .filter(|(path, modified)| files_before.get(path) != Some(modified))
+                    .map(|(path, _)| path.to_str().unwrap().to_owned())
+                    .collect();
+                data_paths.sort();
+            }
 
             // Restore the repo to the state it was in before running the script
             if let (true, Some(checkout_res)) = (using_git, checkout_res) {
@@ -73,11 +147,65 @@ impl Transform {
 
         let new_data = Data {
             id,
+            uid: new_uid(),
             md: HashMap::new(),
-            paths: data_paths
+            paths: data_paths,
+            hashes: vec![],
+            sizes: vec![],
+            sidecars: vec![],
+            filter: TreeFilter::default(),
+            trees: vec![],
+            stored: false
         };
         Ok(new_data)
     }
+
+    /// The scripts tracked by git whose file changed in HEAD since the recorded commit
+    pub fn outdated_scripts(&self) -> Vec<String> {
+        self.script_paths
+            .iter()
+            .zip(&self.script_git_hashes)
+            .filter(|(path, hash)| hash.as_ref().is_some_and(|hash| Self::script_changed(path, hash).unwrap_or(false)))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// The git hashes of the scripts at HEAD (scripts that are not tracked keep their hash)
+    pub fn head_hashes(&self) -> Vec<Option<String>> {
+        self.script_paths
+            .iter()
+            .zip(&self.script_git_hashes)
+            .map(|(path, hash)| {
+                let script_dir = Path::new(path).parent().unwrap_or_else(|| Path::new("/"));
+                let head = git2::Repository::discover(script_dir).ok().and_then(|repo| repo.head().ok()?.target());
+                match (hash, head) {
+                    (Some(_), Some(head)) => Some(head.to_string()),
+                    _ => hash.clone()
+                }
+            })
+            .collect()
+    }
+
+    fn script_changed(path_str: &str, hash: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let path = Path::new(path_str).canonicalize()?;
+        let repo = git2::Repository::discover(path.parent().unwrap_or_else(|| Path::new("/")))?;
+        let workdir = repo.workdir().ok_or("The repository has no working directory")?.canonicalize()?;
+        let rel_path = path.strip_prefix(&workdir)?;
+
+        // Compare the blob of the script at the recorded commit with the one at HEAD
+        let recorded_blob = repo.find_commit(git2::Oid::from_str(hash)?)?.tree()?.get_path(rel_path).map(|e| e.id()).ok();
+        let head_blob = repo.head()?.peel_to_tree()?.get_path(rel_path).map(|e| e.id()).ok();
+        Ok(recorded_blob != head_blob)
+    }
+
+    fn snapshot_dir(dir: &Path) -> Result<HashMap<PathBuf, Option<SystemTime>>, Box<dyn std::error::Error>> {
+        let mut snapshot = HashMap::new();
+        for path in utils::list_files(dir)? {
+            let modified = path.metadata().and_then(|md| md.modified()).ok();
+            snapshot.insert(path, modified);
+        }
+        Ok(snapshot)
+    }
 }
 
 impl std::cmp::PartialEq for Transform {
@@ -88,8 +216,21 @@ impl std::cmp::PartialEq for Transform {
     }
 }
 
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let scripts: Vec<String> = self.script_paths.iter().zip(&self.script_args).map(|(path, args)| {
+            if args.is_empty() { path.clone() } else { format!("{} {}", path, shell_words::join(args)) }
+        }).collect();
+        write!(f, "transform {}: {}", self.id, scripts.join(" | "))
+    }
+}
+
 impl Entry for Transform {
     fn get_id(&self) -> u64 {
         self.id
     }
+
+    fn get_uid(&self) -> &str {
+        &self.uid
+    }
 }
\ No newline at end of file
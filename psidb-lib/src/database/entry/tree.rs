@@ -0,0 +1,124 @@
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use crate::utils;
+
+/// Which files of a directory dataset are tracked, as globs relative to the directory (e.g. `**/*.h5`)
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct TreeFilter {
+    pub include: Vec<String>, // Every file if empty
+    pub exclude: Vec<String>
+}
+
+impl TreeFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    // The compiled include and exclude globs
+    fn compile(&self) -> Result<(GlobSet, GlobSet), Box<dyn Error>> {
+        let build = |globs: &[String]| {
+            let mut builder = GlobSetBuilder::new();
+            for glob in globs {
+                match Glob::new(glob) {
+                    Ok(glob) => builder.add(glob),
+                    Err(e) => {
+                        println!("Error: Invalid glob {}: {}", glob, e);
+                        return Err(format!("Invalid glob {}: {}", glob, e));
+                    }
+                };
+            }
+            builder.build().map_err(|e| e.to_string())
+        };
+        Ok((build(&self.include)?, build(&self.exclude)?))
+    }
+}
+
+/// A file of a directory dataset
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct TreeFile {
+    pub path: String, // Relative to the directory
+    pub size: u64,
+    pub hash: String
+}
+
+/// The manifest of a directory dataset: the tracked files it contained when it was hashed
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Tree {
+    pub root: String,
+    pub files: Vec<TreeFile>
+}
+
+/// How a file inside a tracked directory differs from its manifest
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Added,
+    Removed,
+    Modified
+}
+
+impl std::fmt::Display for FileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FileChange::Added => write!(f, "added"),
+            FileChange::Removed => write!(f, "removed"),
+            FileChange::Modified => write!(f, "modified")
+        }
+    }
+}
+
+impl Tree {
+    /// Lists and hashes the files under root that the filter tracks
+    pub fn scan(root: &str, filter: &TreeFilter) -> Result<Tree, Box<dyn Error>> {
+        let (include, exclude) = filter.compile()?;
+        let mut files = vec![];
+        for file in utils::list_files(Path::new(root))? {
+            let relative = file.strip_prefix(root)?;
+            if (!filter.include.is_empty() && !include.is_match(relative)) || exclude.is_match(relative) {
+                continue;
+            }
+            files.push(TreeFile {
+                path: relative.to_str().unwrap().to_owned(),
+                size: fs::metadata(&file)?.len(),
+                hash: utils::hash_file(&file)?
+            });
+        }
+        Ok(Tree { root: root.to_owned(), files })
+    }
+
+    /// The hash of the whole directory (the same as `utils::hash_path` when every file is tracked)
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        for file in &self.files {
+            hasher.update(file.path.as_bytes());
+            hasher.update(file.hash.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// The files that were added, removed or modified in other (a newer scan of the directory)
+    pub fn diff(&self, other: &Tree) -> Vec<(String, FileChange)> {
+        let hashes: HashMap<&str, &str> = self.files.iter().map(|f| (f.path.as_str(), f.hash.as_str())).collect();
+        let other_hashes: HashMap<&str, &str> = other.files.iter().map(|f| (f.path.as_str(), f.hash.as_str())).collect();
+        let mut changes: Vec<(String, FileChange)> = other.files
+            .iter()
+            .filter_map(|f| match hashes.get(f.path.as_str()) {
+                None => Some((f.path.clone(), FileChange::Added)),
+                Some(hash) if *hash != f.hash => Some((f.path.clone(), FileChange::Modified)),
+                _ => None
+            })
+            .chain(self.files.iter().filter(|f| !other_hashes.contains_key(f.path.as_str())).map(|f| (f.path.clone(), FileChange::Removed)))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    /// Whether the manifest tracks the file with the given path relative to the directory
+    pub fn contains(&self, relative: &Path) -> bool {
+        self.files.iter().any(|f| Path::new(&f.path) == relative)
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::error::Error;
+use std::path::Path;
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use super::Database;
+use super::entry::data::Data;
+use super::entry::metadata::{Metadata, MetaValue};
+use crate::utils;
+
+/// Reads facts about a data file or directory as metadata. Extractors are enabled by name with the `extractors`
+/// setting, and custom ones (e.g. for in-house formats) are added with `Database::register_extractor`.
+pub trait MetadataExtractor: Send + Sync {
+    /// The name the `extractors` setting refers to the extractor by
+    fn name(&self) -> &str;
+
+    /// The metadata of the path, empty if the extractor does not apply to it
+    fn extract(&self, path: &Path) -> Result<Metadata, Box<dyn Error>>;
+}
+
+/// The size and modification time of a file, and the number of files and total size of a directory
+pub struct Stat;
+
+impl MetadataExtractor for Stat {
+    fn name(&self) -> &str {
+        "stat"
+    }
+
+    fn extract(&self, path: &Path) -> Result<Metadata, Box<dyn Error>> {
+        let metadata = fs::metadata(path)?;
+        let mtime: DateTime<Utc> = metadata.modified()?.into();
+        let mut md = Metadata::from([("mtime".to_owned(), MetaValue::DateTime(mtime.into()))]);
+        if metadata.is_dir() {
+            let files = utils::list_files(path)?;
+            let size = files.iter().map(|file| fs::metadata(file).map(|m| m.len())).sum::<Result<u64, _>>()?;
+            md.insert("size".to_owned(), MetaValue::Int(size as i64));
+            md.insert("file_count".to_owned(), MetaValue::Int(files.len() as i64));
+        } else {
+            md.insert("size".to_owned(), MetaValue::Int(metadata.len() as i64));
+        }
+        Ok(md)
+    }
+}
+
+/// The MIME type of a file, detected from its first bytes (and its extension for text files)
+pub struct Mime;
+
+// The MIME types of formats that start with a known sequence of bytes
+const MAGIC: [(&[u8], &str); 18] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"II*\x00", "image/tiff"),
+    (b"MM\x00*", "image/tiff"),
+    (b"%PDF", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"BZh", "application/x-bzip2"),
+    (b"\xfd7zXZ\x00", "application/x-xz"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\x89HDF\r\n\x1a\n", "application/x-hdf5"),
+    (b"CDF\x01", "application/x-netcdf"),
+    (b"CDF\x02", "application/x-netcdf"),
+    (b"PAR1", "application/vnd.apache.parquet"),
+    (b"\x93NUMPY", "application/x-npy"),
+    (b"SIMPLE  =", "application/fits"),
+    (b"\x7fELF", "application/x-executable")
+];
+
+impl MetadataExtractor for Mime {
+    fn name(&self) -> &str {
+        "mime"
+    }
+
+    fn extract(&self, path: &Path) -> Result<Metadata, Box<dyn Error>> {
+        if path.is_dir() {
+            return Ok(Metadata::new());
+        }
+        let mut head = Vec::with_capacity(4096);
+        fs::File::open(path)?.take(4096).read_to_end(&mut head)?;
+
+        let mime = if head.is_empty() {
+            "inode/x-empty"
+        } else if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| head.starts_with(magic)) {
+            mime
+        } else if !head.contains(&0) && std::str::from_utf8(&head).map_or_else(|e| e.error_len().is_none(), |_| true) {
+            // Text (the first bytes may end in the middle of a character)
+            match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+                Some("csv") => "text/csv",
+                Some("tsv") => "text/tab-separated-values",
+                Some("json") => "application/json",
+                Some("yaml" | "yml") => "application/yaml",
+                Some("toml") => "application/toml",
+                _ => "text/plain"
+            }
+        } else {
+            "application/octet-stream"
+        };
+        Ok(Metadata::from([("mime".to_owned(), mime.into())]))
+    }
+}
+
+/// The number of lines and the header row of a CSV or TSV file
+pub struct Table;
+
+impl MetadataExtractor for Table {
+    fn name(&self) -> &str {
+        "table"
+    }
+
+    fn extract(&self, path: &Path) -> Result<Metadata, Box<dyn Error>> {
+        let delimiter = match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+            Some("csv") => b',',
+            Some("tsv") => b'\t',
+            _ => return Ok(Metadata::new())
+        };
+        if path.is_dir() {
+            return Ok(Metadata::new());
+        }
+
+        let lines = BufReader::new(fs::File::open(path)?).split(b'\n').count();
+        let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).from_path(path)?;
+        let header = reader.headers()?.iter().map(|column| column.into()).collect();
+        Ok(Metadata::from([
+            ("lines".to_owned(), MetaValue::Int(lines as i64)),
+            ("header".to_owned(), MetaValue::List(header))
+        ]))
+    }
+}
+
+// The extractors that come with psidb
+fn builtin_extractors() -> Vec<Box<dyn MetadataExtractor>> {
+    vec![Box::new(Stat), Box::new(Mime), Box::new(Table)]
+}
+
+impl Database {
+    /// Makes an extractor available to the `extractors` setting, replacing the extractor with the same name
+    pub fn register_extractor(&mut self, extractor: Box<dyn MetadataExtractor>) {
+        self.extractors.retain(|e| e.name() != extractor.name());
+        self.extractors.push(extractor);
+    }
+
+    /// The names of the extractors that the `extractors` setting can enable
+    pub fn get_extractor_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = builtin_extractors().iter().chain(&self.extractors).map(|e| e.name().to_owned()).collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Adds the metadata of the extractors enabled in the settings to the data. Keys the data already has are kept.
+    /// Data with a single path gets the values directly, data with several paths gets a map from path to value.
+    pub(super) fn extract_md(&self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        let builtins = builtin_extractors();
+        let mut extracted: BTreeMap<String, BTreeMap<String, MetaValue>> = BTreeMap::new();
+        for name in &self.settings.extractors {
+            // Registered extractors take precedence over the built-in ones
+            let Some(extractor) = self.extractors.iter().chain(&builtins).find(|e| e.name() == name) else {
+                println!("Error: Unknown metadata extractor {}, expected one of {}", name, self.get_extractor_names().join(", "));
+                return Err(format!("Unknown metadata extractor {}, expected one of {}", name, self.get_extractor_names().join(", ")).into());
+            };
+            for path in &data.paths {
+                let md = extractor.extract(Path::new(path)).map_err(|e| {
+                    println!("Error: The metadata extractor {} failed on {}: {}", name, path, e);
+                    format!("The metadata extractor {} failed on {}: {}", name, path, e)
+                })?;
+                for (key, value) in md {
+                    extracted.entry(key).or_default().insert(path.clone(), value);
+                }
+            }
+        }
+
+        for (key, mut values) in extracted {
+            let value = if data.paths.len() == 1 { values.pop_first().unwrap().1 } else { MetaValue::Map(values) };
+            data.md.entry(key).or_insert(value);
+        }
+        Ok(())
+    }
+}
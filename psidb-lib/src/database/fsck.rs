@@ -0,0 +1,211 @@
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use super::Database;
+use super::entry::{new_uid, action::Action};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Warning, // Something unusual that psidb copes with
+    Error // Something that breaks commands or makes their results wrong
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+}
+
+/// A problem with the contents of a database, e.g. after db.ron was edited by hand
+#[derive(Clone, PartialEq, Debug)]
+pub enum Issue {
+    DuplicateId { id: u64, count: usize }, // Several entries have the same id
+    DuplicateUid { uid: String, ids: Vec<u64> }, // Several entries have the same uid
+    CurrIdTooLow { curr_id: u64, max_id: u64 }, // The next id is already used
+    DanglingReference { connection_id: u64, id: u64, kind: &'static str }, // A connection refers to a missing entry
+    ApplyWithoutTransform { connection_id: u64 }, // An apply connection does not say which transform made its output
+    EmptyPaths { data_id: u64 }, // A dataset has no paths
+    DuplicatePaths { data_id: u64, other_id: u64 }, // Two datasets have the same paths
+    HashCount { data_id: u64 }, // A dataset does not have one hash per path
+    ScriptCount { transform_id: u64 }, // A transform does not have arguments and a commit for each script
+    InputHashCount { connection_id: u64 }, // An apply connection does not have one hash per input
+    UnknownTemplate { setting: &'static str, name: String } // A default template does not exist
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::DuplicateUid { .. } | Issue::HashCount { .. } | Issue::InputHashCount { .. } | Issue::UnknownTemplate { .. } => Severity::Warning,
+            _ => Severity::Error
+        }
+    }
+
+    /// Whether `Database::repair` can fix the issue
+    pub fn fixable(&self) -> bool {
+        matches!(self, Issue::DuplicateUid { .. } | Issue::CurrIdTooLow { .. } | Issue::DanglingReference { .. } | Issue::InputHashCount { .. } | Issue::UnknownTemplate { .. })
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::DuplicateId { id, count } => write!(f, "{} entries have the id {}", count, id),
+            Issue::DuplicateUid { uid, ids } => {
+                let ids: Vec<String> = ids.iter().map(u64::to_string).collect();
+                write!(f, "entries {} have the same uid {}", ids.join(", "), uid)
+            }
+            Issue::CurrIdTooLow { curr_id, max_id } => write!(f, "the next id is {} but id {} is already used", curr_id, max_id),
+            Issue::DanglingReference { connection_id, id, kind } => write!(f, "connection {} refers to {} {}, which does not exist", connection_id, kind, id),
+            Issue::ApplyWithoutTransform { connection_id } => write!(f, "apply connection {} has no transform", connection_id),
+            Issue::EmptyPaths { data_id } => write!(f, "data {} has no paths", data_id),
+            Issue::DuplicatePaths { data_id, other_id } => write!(f, "data {} has the same paths as data {}", data_id, other_id),
+            Issue::HashCount { data_id } => write!(f, "data {} does not have one hash per path", data_id),
+            Issue::ScriptCount { transform_id } => write!(f, "transform {} does not have arguments and a commit for each script", transform_id),
+            Issue::InputHashCount { connection_id } => write!(f, "apply connection {} does not have one hash per input", connection_id),
+            Issue::UnknownTemplate { setting, name } => write!(f, "the {} setting names template {}, which does not exist", setting, name)
+        }
+    }
+}
+
+impl Database {
+    /// Checks that the database is consistent: ids, references between entries, paths and settings
+    pub fn check(&self) -> Vec<Issue> {
+        let mut issues = vec![];
+
+        // Ids are unique across kinds, and below the next id
+        let ids = self.data_vec.iter().map(|d| d.id).chain(self.transform_vec.iter().map(|t| t.id)).chain(self.connection_vec.iter().map(|c| c.id));
+        let mut id_counts: HashMap<u64, usize> = HashMap::new();
+        for id in ids {
+            *id_counts.entry(id).or_default() += 1;
+        }
+        let mut duplicate_ids: Vec<(u64, usize)> = id_counts.iter().filter(|(_, count)| **count > 1).map(|(id, count)| (*id, *count)).collect();
+        duplicate_ids.sort_unstable();
+        issues.extend(duplicate_ids.into_iter().map(|(id, count)| Issue::DuplicateId { id, count }));
+        if let Some(&max_id) = id_counts.keys().max().filter(|max_id| **max_id >= self.curr_id) {
+            issues.push(Issue::CurrIdTooLow { curr_id: self.curr_id, max_id });
+        }
+
+        let uids = self.data_vec.iter().map(|d| (d.id, &d.uid))
+            .chain(self.transform_vec.iter().map(|t| (t.id, &t.uid)))
+            .chain(self.connection_vec.iter().map(|c| (c.id, &c.uid)));
+        let mut uid_ids: HashMap<&String, Vec<u64>> = HashMap::new();
+        for (id, uid) in uids {
+            uid_ids.entry(uid).or_default().push(id);
+        }
+        let mut duplicate_uids: Vec<Issue> = uid_ids
+            .into_iter()
+            .filter(|(_, ids)| ids.len() > 1)
+            .map(|(uid, ids)| Issue::DuplicateUid { uid: uid.clone(), ids })
+            .collect();
+        duplicate_uids.sort_by_key(|issue| if let Issue::DuplicateUid { ids, .. } = issue { ids[0] } else { 0 });
+        issues.extend(duplicate_uids);
+
+        // Connections refer to existing entries of the right kind
+        let data_ids: HashSet<u64> = self.data_vec.iter().map(|d| d.id).collect();
+        let transform_ids: HashSet<u64> = self.transform_vec.iter().map(|t| t.id).collect();
+        for connection in &self.connection_vec {
+            let data_refs = connection.in_data_ids.iter().chain(&connection.out_data_ids).filter(|id| !data_ids.contains(id)).map(|id| (*id, "data"));
+            let transform_refs = connection.in_transform_ids.iter().chain(&connection.out_transform_ids).filter(|id| !transform_ids.contains(id)).map(|id| (*id, "transform"));
+            for (id, kind) in data_refs.chain(transform_refs) {
+                issues.push(Issue::DanglingReference { connection_id: connection.id, id, kind });
+            }
+            if connection.action == Action::Apply {
+                if connection.in_transform_ids.is_empty() {
+                    issues.push(Issue::ApplyWithoutTransform { connection_id: connection.id });
+                }
+                if !connection.in_data_hashes.is_empty() && connection.in_data_hashes.len() != connection.in_data_ids.len() {
+                    issues.push(Issue::InputHashCount { connection_id: connection.id });
+                }
+            }
+        }
+
+        // Datasets have paths, with a hash each, and no two have the same paths
+        let mut paths_ids: HashMap<Vec<&String>, u64> = HashMap::new();
+        for data in &self.data_vec {
+            if data.paths.is_empty() {
+                issues.push(Issue::EmptyPaths { data_id: data.id });
+                continue;
+            }
+            if !data.hashes.is_empty() && data.hashes.len() != data.paths.len() {
+                issues.push(Issue::HashCount { data_id: data.id });
+            }
+            let mut paths: Vec<&String> = data.paths.iter().collect();
+            paths.sort_unstable();
+            if let Some(other_id) = paths_ids.insert(paths, data.id) {
+                issues.push(Issue::DuplicatePaths { data_id: data.id, other_id });
+            }
+        }
+        for transform in &self.transform_vec {
+            if transform.script_args.len() != transform.script_paths.len() || transform.script_git_hashes.len() != transform.script_paths.len() {
+                issues.push(Issue::ScriptCount { transform_id: transform.id });
+            }
+        }
+
+        for (setting, name) in [("data_template", &self.settings.data_template), ("transform_template", &self.settings.transform_template)] {
+            if let Some(name) = name.as_ref().filter(|name| self.get_template(name).is_none()) {
+                issues.push(Issue::UnknownTemplate { setting, name: name.clone() });
+            }
+        }
+        issues
+    }
+
+    /// Fixes the issues that can be fixed mechanically (see `Issue::fixable`) and returns them
+    pub fn repair(&mut self) -> Vec<Issue> {
+        let fixed: Vec<Issue> = self.check().into_iter().filter(Issue::fixable).collect();
+        let mut trimmed = HashSet::new();
+        for issue in &fixed {
+            match issue {
+                Issue::CurrIdTooLow { max_id, .. } => self.curr_id = max_id + 1,
+                Issue::DanglingReference { connection_id, id, kind } => {
+                    trimmed.insert(*connection_id);
+                    for connection in self.connection_vec.iter_mut().filter(|c| c.id == *connection_id) {
+                        if *kind == "data" {
+                            // The input hashes go with the input ids
+                            if connection.in_data_hashes.len() == connection.in_data_ids.len() {
+                                let hashes = std::mem::take(&mut connection.in_data_hashes);
+                                connection.in_data_hashes = connection.in_data_ids.iter().zip(hashes).filter(|(i, _)| *i != id).map(|(_, h)| h).collect();
+                            }
+                            connection.in_data_ids.retain(|i| i != id);
+                            connection.out_data_ids.retain(|i| i != id);
+                        } else {
+                            connection.in_transform_ids.retain(|i| i != id);
+                            connection.out_transform_ids.retain(|i| i != id);
+                        }
+                    }
+                }
+                Issue::InputHashCount { connection_id } => {
+                    for connection in self.connection_vec.iter_mut().filter(|c| c.id == *connection_id) {
+                        connection.in_data_hashes.clear();
+                    }
+                }
+                Issue::UnknownTemplate { setting, .. } => {
+                    if *setting == "data_template" {
+                        self.settings.data_template = None;
+                    } else {
+                        self.settings.transform_template = None;
+                    }
+                }
+                _ => ()
+            }
+        }
+
+        // Entries after the first with a uid get a new one
+        if fixed.iter().any(|issue| matches!(issue, Issue::DuplicateUid { .. })) {
+            let mut seen = HashSet::new();
+            let uids = self.data_vec.iter_mut().map(|d| &mut d.uid)
+                .chain(self.transform_vec.iter_mut().map(|t| &mut t.uid))
+                .chain(self.connection_vec.iter_mut().map(|c| &mut c.uid));
+            for uid in uids {
+                if !seen.insert(uid.clone()) {
+                    *uid = new_uid();
+                }
+            }
+        }
+        // Connections left with nothing to connect go
+        self.connection_vec.retain(|c| !trimmed.contains(&c.id) || c.in_ids().next().is_some() || c.out_ids().next().is_some());
+        self.reindex();
+        fixed
+    }
+}
@@ -0,0 +1,45 @@
+use super::Database;
+use super::entry::action::Action;
+
+impl Database {
+    /// The ids of the data and transforms the entry with the given id was made from
+    pub fn parents(&self, id: u64) -> Vec<u64> {
+        let mut parents: Vec<u64> = self.in_connections(id).iter().flat_map(|c| c.in_ids()).collect();
+        parents.sort_unstable();
+        parents.dedup();
+        parents
+    }
+
+    /// The ids of the data and transforms made from the entry with the given id
+    pub fn children(&self, id: u64) -> Vec<u64> {
+        let mut children: Vec<u64> = self.out_connections(id).iter().flat_map(|c| c.out_ids()).collect();
+        children.sort_unstable();
+        children.dedup();
+        children
+    }
+
+    /// The ids of the newer versions of the entry with the given id (made by `update`)
+    pub fn newer_versions(&self, id: u64) -> Vec<u64> {
+        self.out_connections(id).iter().filter(|c| c.action == Action::Update).flat_map(|c| c.out_ids()).collect()
+    }
+
+    /// The provenance graph in the Graphviz DOT format (render it with e.g. `dot -Tsvg`)
+    pub fn to_dot(&self) -> String {
+        // Labels are quoted, so only quotes and backslashes need escaping
+        let label = |s: String| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = vec!["digraph psidb {".to_owned()];
+        for data in &self.data_vec {
+            dot.push(format!("    {} [shape=ellipse, label=\"{}\"];", data.id, label(data.to_string())));
+        }
+        for transform in &self.transform_vec {
+            dot.push(format!("    {} [shape=box, label=\"{}\"];", transform.id, label(transform.to_string())));
+        }
+        for connection in &self.connection_vec {
+            dot.push(format!("    {} [shape=diamond, label=\"{} {}\"];", connection.id, connection.action, connection.id));
+            dot.extend(connection.in_ids().map(|id| format!("    {} -> {};", id, connection.id)));
+            dot.extend(connection.out_ids().map(|id| format!("    {} -> {};", connection.id, id)));
+        }
+        dot.push("}".to_owned());
+        dot.join("\n")
+    }
+}
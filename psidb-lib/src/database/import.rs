@@ -0,0 +1,272 @@
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+use std::collections::{HashMap, BTreeMap};
+use clap::ValueEnum;
+use regex::Regex;
+use globset::Glob;
+use walkdir::WalkDir;
+use super::Database;
+use super::entry::transform::Transform;
+use super::entry::metadata::{Metadata, MetaValue};
+
+/// How the imported files are split into datasets
+pub enum GroupBy {
+    File, // One dataset per file
+    Dir, // One dataset per folder
+    Key(String) // One dataset per value of a named capture of the metadata regex
+}
+
+impl From<&str> for GroupBy {
+    fn from(s: &str) -> GroupBy {
+        match s {
+            "file" => GroupBy::File,
+            "dir" => GroupBy::Dir,
+            key => GroupBy::Key(key.to_owned())
+        }
+    }
+}
+
+/// The kind of entries the rows of a manifest describe
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ManifestKind {
+    Data,
+    Transform
+}
+
+/// The columns of a manifest that are not metadata
+pub struct ManifestColumns<'a> {
+    pub paths: &'a str, // The paths of the files or scripts (relative to the folder of the manifest)
+    pub args: Option<&'a str>, // The arguments of the scripts, a shell-quoted string or a JSON list
+    pub group_by: Option<&'a str> // The rows with the same value make up a single entry
+}
+
+// The paths, script arguments and metadata of a row of a manifest
+type ManifestRow = (Vec<String>, Vec<String>, Metadata);
+
+pub struct ImportedEntry {
+    pub id: Option<u64>, // None in a dry run
+    pub paths: Vec<String>, // The paths of the data or of the scripts of the transform
+    pub md: Metadata
+}
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub added: Vec<ImportedEntry>,
+    pub existing: Vec<(u64, Vec<String>)>, // Entries that are already in the database, with their id
+    pub unmatched: Vec<String> // Files that match the glob but not the metadata regex (they are not imported)
+}
+
+// The metadata that all the files or rows of an entry agree on
+fn common_md(mds: &[Metadata]) -> Metadata {
+    let mut md = mds.first().cloned().unwrap_or_default();
+    md.retain(|k, v| mds.iter().all(|other| other.get(k) == Some(v)));
+    md
+}
+
+// The rows of a CSV file (with a header) or of a JSON array of objects. Empty cells and nulls are left out. The CSV
+// cells of raw_columns stay strings (e.g. a file named 007), the types of the other cells are inferred.
+fn read_manifest(manifest_path: &str, raw_columns: &[&str]) -> Result<Vec<Metadata>, Box<dyn Error>> {
+    let rows: Result<Vec<Metadata>, String> = match Path::new(manifest_path).extension().and_then(|e| e.to_str()) {
+        Some("csv") => csv::Reader::from_path(manifest_path)
+            .and_then(|mut reader| {
+                let headers = reader.headers()?.clone();
+                reader.records().map(|record| Ok(headers.iter()
+                    .zip(record?.iter())
+                    .filter(|(_, cell)| !cell.is_empty())
+                    .map(|(header, cell)| {
+                        let value = if raw_columns.contains(&header) { MetaValue::String(cell.to_owned()) } else { MetaValue::infer(cell) };
+                        (header.to_owned(), value)
+                    })
+                    .collect())).collect()
+            })
+            .map_err(|e| e.to_string()),
+        Some("json") => fs::read_to_string(manifest_path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| serde_json::from_str::<Vec<BTreeMap<String, Option<MetaValue>>>>(&contents).map_err(|e| e.to_string()))
+            .map(|rows| rows.into_iter().map(|row| row.into_iter().filter_map(|(k, v)| Some((k, v?))).collect()).collect()),
+        _ => Err("expected a .csv or .json file".to_owned())
+    };
+    rows.map_err(|e| {
+        println!("Error: Could not read the manifest {}: {}", manifest_path, e);
+        format!("Could not read the manifest {}: {}", manifest_path, e).into()
+    })
+}
+
+impl Database {
+    /// Adds the files under dir that match glob (relative to dir, e.g. `**/*.h5`) as datasets. The named captures of
+    /// md_regex (matched against the relative path) become metadata, along with meta_data_str. Datasets that are
+    /// already in the database are skipped, and nothing is added in a dry run.
+    pub fn import(&mut self, dir: &str, glob: &str, md_regex: Option<&str>, group_by: &GroupBy, meta_data_str: Option<&str>, dry_run: bool) -> Result<ImportReport, Box<dyn Error>> {
+        let matcher = match Glob::new(glob) {
+            Ok(glob) => glob.compile_matcher(),
+            Err(e) => {
+                println!("Error: Invalid glob {}: {}", glob, e);
+                return Err(format!("Invalid glob {}: {}", glob, e).into());
+            }
+        };
+        let md_regex = match md_regex.map(Regex::new).transpose() {
+            Ok(md_regex) => md_regex,
+            Err(e) => {
+                println!("Error: Invalid metadata regex: {}", e);
+                return Err(format!("Invalid metadata regex: {}", e).into());
+            }
+        };
+        if let GroupBy::Key(key) = group_by {
+            if !md_regex.as_ref().is_some_and(|re| re.capture_names().any(|name| name == Some(key.as_str()))) {
+                println!("Error: Cannot group by {}, which is not a named capture of the metadata regex (or file or dir)", key);
+                return Err(format!("Cannot group by {}, which is not a named capture of the metadata regex", key).into());
+            }
+        }
+        let given_md = Self::parse_md(meta_data_str)?;
+        if !Path::new(dir).is_dir() {
+            println!("Error: {} is not a directory", dir);
+            return Err(format!("{} is not a directory", dir).into());
+        }
+
+        // Group the matching files (in a stable order, so that ids are reproducible)
+        let mut report = ImportReport::default();
+        let mut groups: BTreeMap<String, Vec<(String, Metadata)>> = BTreeMap::new();
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(dir)?;
+            if !entry.file_type().is_file() || !matcher.is_match(relative) {
+                continue;
+            }
+            let path = entry.path().to_str().unwrap().to_owned();
+            let relative = relative.to_str().unwrap();
+
+            let mut md = Metadata::new();
+            if let Some(re) = &md_regex {
+                let Some(captures) = re.captures(relative) else {
+                    report.unmatched.push(entry.path().canonicalize()?.to_str().unwrap().to_owned());
+                    continue;
+                };
+                for name in re.capture_names().flatten() {
+                    if let Some(value) = captures.name(name) {
+                        md.insert(name.to_owned(), MetaValue::infer(value.as_str()));
+                    }
+                }
+            }
+            let group = match group_by {
+                GroupBy::File => path.clone(),
+                GroupBy::Dir => entry.path().parent().unwrap().to_str().unwrap().to_owned(),
+                GroupBy::Key(key) => md.get(key).map_or_else(String::new, MetaValue::to_string)
+            };
+            groups.entry(group).or_default().push((path, md));
+        }
+
+        for files in groups.into_values() {
+            let (paths, mds): (Vec<String>, Vec<Metadata>) = files.into_iter().unzip();
+            let mut md = common_md(&mds);
+            md.extend(given_md.clone());
+            self.import_data(&paths, md, dry_run, &mut report)?;
+        }
+        self.store_imported(&report)?;
+        Ok(report)
+    }
+
+    // Moves the files of the imported datasets into the object store if the store setting is on. This happens once
+    // every entry was added, so that a failing entry leaves the files where they are.
+    fn store_imported(&mut self, report: &ImportReport) -> Result<(), Box<dyn Error>> {
+        if self.settings.store {
+            for id in report.added.iter().filter_map(|entry| entry.id) {
+                self.store_data(id)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Adds a dataset like `add_data` does, unless it is already in the database
+    fn import_data(&mut self, paths: &[String], md: Metadata, dry_run: bool, report: &mut ImportReport) -> Result<(), Box<dyn Error>> {
+        let mut data = self.make_data(paths, md)?;
+        self.apply_template(&mut data.md, self.settings.data_template.as_ref())?;
+        if let Some(id) = self.find_data(&data) {
+            report.existing.push((id, data.paths));
+            return Ok(());
+        }
+        let (paths, md) = (data.paths.clone(), data.md.clone());
+        let id = if dry_run { None } else { Some(self.try_add_data(data)?) };
+        report.added.push(ImportedEntry { id, paths, md });
+        Ok(())
+    }
+
+    // Adds a transform like `add_transform` does, unless it is already in the database
+    fn import_transform(&mut self, script_paths: &[String], script_args: &[Vec<String>], mut md: Metadata, dry_run: bool, report: &mut ImportReport) -> Result<(), Box<dyn Error>> {
+        self.apply_template(&mut md, self.settings.transform_template.as_ref())?;
+        let transform = self.make_transform(script_paths, Some(script_args), None, md)?;
+        if let Some(id) = self.find_transform(&transform) {
+            report.existing.push((id, transform.script_paths));
+            return Ok(());
+        }
+        let (paths, md) = (transform.script_paths.clone(), transform.md.clone());
+        let id = if dry_run { None } else { Some(self.try_add_transform(transform)?) };
+        report.added.push(ImportedEntry { id, paths, md });
+        Ok(())
+    }
+
+    /// Adds the datasets or transforms listed in a manifest, a CSV file or a JSON array of objects with one row per
+    /// file or script. The columns other than those in columns become metadata. Entries that are already in the
+    /// database are skipped, and nothing is added in a dry run.
+    pub fn import_manifest(&mut self, manifest_path: &str, kind: ManifestKind, columns: &ManifestColumns, meta_data_str: Option<&str>, dry_run: bool) -> Result<ImportReport, Box<dyn Error>> {
+        let ManifestColumns { paths: paths_col, args: args_col, group_by } = *columns;
+        let raw_columns: Vec<&str> = [Some(paths_col), args_col, group_by].into_iter().flatten().collect();
+        let rows = read_manifest(manifest_path, &raw_columns)?;
+        let given_md = Self::parse_md(meta_data_str)?;
+        let base_dir = Path::new(manifest_path).parent().unwrap_or_else(|| Path::new(""));
+
+        // Group the rows (paths, script arguments and metadata), keeping the order of the manifest
+        let mut groups: Vec<Vec<ManifestRow>> = vec![];
+        let mut group_positions: HashMap<String, usize> = HashMap::new();
+        for (i, mut row) in rows.into_iter().enumerate() {
+            // A cell holds one path, a JSON list several
+            let paths = match row.remove(paths_col) {
+                Some(MetaValue::List(paths)) => paths,
+                Some(path) => vec![path],
+                None => {
+                    println!("Error: Row {} of {} has no {}", i + 1, manifest_path, paths_col);
+                    return Err(format!("Row {} of {} has no {}", i + 1, manifest_path, paths_col).into());
+                }
+            };
+            let paths = paths.iter().map(|p| base_dir.join(p.to_string()).to_str().unwrap().to_owned()).collect();
+            let args = match args_col.and_then(|col| row.remove(col)) {
+                Some(MetaValue::List(args)) => args.iter().map(MetaValue::to_string).collect(),
+                Some(args) => Transform::split_args(&[args.to_string()])?.remove(0),
+                None => vec![]
+            };
+
+            match group_by.and_then(|col| row.get(col)).map(MetaValue::to_string) {
+                Some(key) if group_positions.contains_key(&key) => groups[group_positions[&key]].push((paths, args, row)),
+                key => {
+                    if let Some(key) = key {
+                        group_positions.insert(key, groups.len());
+                    }
+                    groups.push(vec![(paths, args, row)]);
+                }
+            }
+        }
+
+        let mut report = ImportReport::default();
+        for group in groups {
+            let mut paths = vec![];
+            let mut script_args = vec![];
+            let mut mds = vec![];
+            for (row_paths, args, md) in group {
+                script_args.extend(std::iter::repeat_n(args, row_paths.len()));
+                paths.extend(row_paths);
+                mds.push(md);
+            }
+
+            let mut md = common_md(&mds);
+            md.extend(given_md.clone());
+            match kind {
+                ManifestKind::Data => self.import_data(&paths, md, dry_run, &mut report)?,
+                ManifestKind::Transform => self.import_transform(&paths, &script_args, md, dry_run, &mut report)?
+            }
+        }
+        if kind == ManifestKind::Data {
+            self.store_imported(&report)?;
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use super::Database;
+use super::entry::{data::Data, transform::Transform, connection::Connection};
+
+/// Lookup tables over the entries of a database, so that finding an entry does not scan every vector. They are
+/// rebuilt when the database is loaded and kept up to date by the methods that add, change or remove entries.
+#[derive(Default)]
+pub(super) struct Index {
+    positions: HashMap<u64, usize>, // Id -> position in the vector of its kind (ids are unique across kinds)
+    paths: HashMap<Vec<String>, u64>, // Sorted paths -> data id
+    scripts: HashMap<Vec<String>, Vec<u64>>, // Script paths -> ids of the transforms running them
+    consumers: HashMap<u64, Vec<u64>>, // Entry id -> ids of the connections that have it as an input
+    producers: HashMap<u64, Vec<u64>> // Entry id -> ids of the connections that have it as an output
+}
+
+fn sorted_paths(data: &Data) -> Vec<String> {
+    let mut paths = data.paths.clone();
+    paths.sort_unstable();
+    paths
+}
+
+impl Database {
+    /// Rebuilds the index from scratch, e.g. after entries were removed
+    pub(super) fn reindex(&mut self) {
+        self.index = Index::default();
+        for pos in 0..self.data_vec.len() {
+            self.index_data(pos);
+        }
+        for pos in 0..self.transform_vec.len() {
+            self.index_transform(pos);
+        }
+        for pos in 0..self.connection_vec.len() {
+            self.index_connection(pos);
+        }
+    }
+
+    fn index_data(&mut self, pos: usize) {
+        let data = &self.data_vec[pos];
+        self.index.positions.insert(data.id, pos);
+        self.index.paths.insert(sorted_paths(data), data.id);
+    }
+
+    fn index_transform(&mut self, pos: usize) {
+        let transform = &self.transform_vec[pos];
+        self.index.positions.insert(transform.id, pos);
+        self.index.scripts.entry(transform.script_paths.clone()).or_default().push(transform.id);
+    }
+
+    fn index_connection(&mut self, pos: usize) {
+        let connection = &self.connection_vec[pos];
+        self.index.positions.insert(connection.id, pos);
+        for id in connection.in_ids() {
+            self.index.consumers.entry(id).or_default().push(connection.id);
+        }
+        for id in connection.out_ids() {
+            self.index.producers.entry(id).or_default().push(connection.id);
+        }
+    }
+
+    // Removes the lookups of the entry with the given id (it stays in its vector)
+    fn unindex(&mut self, id: u64) {
+        let remove_id = |ids: &mut Vec<u64>| ids.retain(|i| *i != id);
+        if let Some(data) = self.get_data(id) {
+            let paths = sorted_paths(data);
+            self.index.paths.remove(&paths);
+        } else if let Some(transform) = self.get_transform(id) {
+            let script_paths = transform.script_paths.clone();
+            self.index.scripts.entry(script_paths).and_modify(remove_id);
+        } else if let Some(connection) = self.get_connection(id) {
+            let (in_ids, out_ids): (Vec<u64>, Vec<u64>) = (connection.in_ids().collect(), connection.out_ids().collect());
+            for in_id in in_ids {
+                self.index.consumers.entry(in_id).and_modify(remove_id);
+            }
+            for out_id in out_ids {
+                self.index.producers.entry(out_id).and_modify(remove_id);
+            }
+        }
+    }
+
+    pub(super) fn push_data(&mut self, data: Data) {
+        self.data_vec.push(data);
+        self.index_data(self.data_vec.len() - 1);
+    }
+
+    pub(super) fn push_transform(&mut self, transform: Transform) {
+        self.transform_vec.push(transform);
+        self.index_transform(self.transform_vec.len() - 1);
+    }
+
+    pub(super) fn push_connection(&mut self, connection: Connection) {
+        self.connection_vec.push(connection);
+        self.index_connection(self.connection_vec.len() - 1);
+    }
+
+    /// Replaces the data with the same id
+    pub(super) fn replace_data(&mut self, data: Data) {
+        self.unindex(data.id);
+        if let Some(&pos) = self.index.positions.get(&data.id) {
+            self.data_vec[pos] = data;
+            self.index_data(pos);
+        }
+    }
+
+    /// Replaces the transform with the same id
+    pub(super) fn replace_transform(&mut self, transform: Transform) {
+        self.unindex(transform.id);
+        if let Some(&pos) = self.index.positions.get(&transform.id) {
+            self.transform_vec[pos] = transform;
+            self.index_transform(pos);
+        }
+    }
+
+    /// Replaces the connection with the same id
+    pub(super) fn replace_connection(&mut self, connection: Connection) {
+        self.unindex(connection.id);
+        if let Some(&pos) = self.index.positions.get(&connection.id) {
+            self.connection_vec[pos] = connection;
+            self.index_connection(pos);
+        }
+    }
+
+    /// Removes the entries with the given ids
+    pub(super) fn remove_entries(&mut self, ids: &[u64]) {
+        self.data_vec.retain(|d| !ids.contains(&d.id));
+        self.transform_vec.retain(|t| !ids.contains(&t.id));
+        self.connection_vec.retain(|c| !ids.contains(&c.id));
+        self.reindex();
+    }
+
+    pub fn get_data(&self, id: u64) -> Option<&Data> {
+        self.index.positions.get(&id).and_then(|pos| self.data_vec.get(*pos)).filter(|d| d.id == id)
+    }
+
+    pub fn get_transform(&self, id: u64) -> Option<&Transform> {
+        self.index.positions.get(&id).and_then(|pos| self.transform_vec.get(*pos)).filter(|t| t.id == id)
+    }
+
+    pub fn get_connection(&self, id: u64) -> Option<&Connection> {
+        self.index.positions.get(&id).and_then(|pos| self.connection_vec.get(*pos)).filter(|c| c.id == id)
+    }
+
+    // The mutable getters must not change the paths, scripts or ids of the entries (use the replace methods instead)
+    pub(super) fn get_data_mut(&mut self, id: u64) -> Option<&mut Data> {
+        self.index.positions.get(&id).and_then(|pos| self.data_vec.get_mut(*pos)).filter(|d| d.id == id)
+    }
+
+    pub(super) fn get_transform_mut(&mut self, id: u64) -> Option<&mut Transform> {
+        self.index.positions.get(&id).and_then(|pos| self.transform_vec.get_mut(*pos)).filter(|t| t.id == id)
+    }
+
+    pub(super) fn get_connection_mut(&mut self, id: u64) -> Option<&mut Connection> {
+        self.index.positions.get(&id).and_then(|pos| self.connection_vec.get_mut(*pos)).filter(|c| c.id == id)
+    }
+
+    /// The id of the data with the same paths, if there is one
+    pub fn find_data(&self, data: &Data) -> Option<u64> {
+        self.index.paths.get(&sorted_paths(data)).copied()
+    }
+
+    /// The id of the transform with the same scripts, arguments and commits, if there is one
+    pub fn find_transform(&self, transform: &Transform) -> Option<u64> {
+        let ids = self.index.scripts.get(&transform.script_paths)?;
+        ids.iter().copied().find(|id| self.get_transform(*id).is_some_and(|t| t == transform))
+    }
+
+    /// The id of the connection with the same action, data and transforms, if there is one
+    pub fn find_connection(&self, connection: &Connection) -> Option<u64> {
+        // Every connection has an input or an output, and a matching connection shares it
+        let ids = match (connection.in_ids().next(), connection.out_ids().next()) {
+            (Some(in_id), _) => self.index.consumers.get(&in_id)?,
+            (None, Some(out_id)) => self.index.producers.get(&out_id)?,
+            (None, None) => return self.connection_vec.iter().find(|c| *c == connection).map(|c| c.id)
+        };
+        ids.iter().copied().find(|id| self.get_connection(*id).is_some_and(|c| c == connection))
+    }
+
+    // The connections with the given ids, in the order of the database
+    fn connections_at(&self, ids: Option<&Vec<u64>>) -> Vec<&Connection> {
+        let mut connections: Vec<&Connection> = ids.map_or(vec![], |ids| ids.iter().filter_map(|id| self.get_connection(*id)).collect());
+        connections.sort_by_key(|c| self.index.positions[&c.id]);
+        connections
+    }
+
+    /// The connections that have the entry with the given id as an output
+    pub fn in_connections(&self, id: u64) -> Vec<&Connection> {
+        self.connections_at(self.index.producers.get(&id))
+    }
+
+    /// The connections that have the entry with the given id as an input
+    pub fn out_connections(&self, id: u64) -> Vec<&Connection> {
+        self.connections_at(self.index.consumers.get(&id))
+    }
+}
@@ -0,0 +1,376 @@
+use std::fs;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc, SecondsFormat};
+use super::Database;
+use crate::utils;
+use super::settings::Settings;
+use super::entry::{data::Data, transform::Transform, connection::Connection};
+
+/* Every `write` appends one event to .psidb/log (one RON value per line) with the entries that were added, modified
+or removed since the database was loaded. Replaying the events in order rebuilds the database. */
+
+// The invocation of the event that starts the log of a database made before the log existed
+pub const IMPORT: &str = "import";
+
+// The invocation of the event with the changes made by upgrading the database to a newer format version
+pub const MIGRATE: &str = "migrate";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Record {
+    Data(Data),
+    Transform(Transform),
+    Connection(Connection)
+}
+
+impl Record {
+    pub fn id(&self) -> u64 {
+        match self {
+            Record::Data(data) => data.id,
+            Record::Transform(transform) => transform.id,
+            Record::Connection(connection) => connection.id
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Record::Data(..) => "data",
+            Record::Transform(..) => "transform",
+            Record::Connection(..) => "connection"
+        }
+    }
+
+    // Whether every field is the same (PartialEq on entries only compares what makes them duplicates)
+    pub(super) fn same_as(&self, other: &Record) -> bool {
+        match (self, other) {
+            (Record::Data(a), Record::Data(b)) => a.uid == b.uid && a.md == b.md && a.paths == b.paths && a.hashes == b.hashes && a.sizes == b.sizes && a.sidecars == b.sidecars && a.filter == b.filter && a.trees == b.trees && a.stored == b.stored,
+            (Record::Transform(a), Record::Transform(b)) => {
+                a.uid == b.uid && a.md == b.md && a.script_paths == b.script_paths && a.script_args == b.script_args && a.script_git_hashes == b.script_git_hashes
+            }
+            (Record::Connection(a), Record::Connection(b)) => {
+                a.uid == b.uid && a.md == b.md && a == b && a.in_data_hashes == b.in_data_hashes
+            }
+            _ => false
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Change {
+    Added(Record),
+    Modified { before: Box<Record>, after: Box<Record> },
+    Removed(Record),
+    Settings { before: Settings, after: Settings }
+}
+
+impl Change {
+    /// The id of the entry that changed (None for settings)
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            Change::Added(record) | Change::Removed(record) => Some(record.id()),
+            Change::Modified { after, .. } => Some(after.id()),
+            Change::Settings { .. } => None
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(record) => write!(f, "added {} {}", record.kind(), record.id()),
+            Change::Modified { after, .. } => write!(f, "modified {} {}", after.kind(), after.id()),
+            Change::Removed(record) => write!(f, "removed {} {}", record.kind(), record.id()),
+            Change::Settings { .. } => write!(f, "changed the settings")
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub seq: u64,
+    pub time: String,
+    pub user: String,
+    pub host: String,
+    pub invocation: String, // The command line that made the changes
+    pub prev_curr_id: u64,
+    pub curr_id: u64,
+    pub changes: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undoes: Vec<u64>, // The seqs of the events that this event reverts (see `Database::undo`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moves: Vec<Move> // The files or folders that the operation moved on disk (see `Database::move_path`)
+}
+
+/// A file or folder moved on disk by an operation, which undoing the operation moves back
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Move {
+    pub src: String,
+    pub dst: String
+}
+
+impl Event {
+    pub fn get_time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.time).ok().map(|t| t.with_timezone(&Utc))
+    }
+}
+
+// The part of an event that numbers it, which every format version has
+#[derive(Deserialize)]
+struct EventSeq {
+    seq: u64
+}
+
+// The last line of a file that is not blank, read from the end so that a long log is not read whole
+fn read_last_line(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    const CHUNK_LEN: u64 = 4096;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file = fs::File::open(path)?;
+    let mut start = file.metadata()?.len();
+    let mut tail = vec![];
+    while start > 0 && !tail.trim_ascii_end().contains(&b'\n') {
+        let chunk_start = start.saturating_sub(CHUNK_LEN);
+        let mut chunk = vec![0; (start - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend(tail);
+        (tail, start) = (chunk, chunk_start);
+    }
+    // The first chunk can start inside a character, but not inside the last line
+    let tail = String::from_utf8_lossy(&tail);
+    Ok(tail.trim_end().lines().last().filter(|line| !line.trim().is_empty()).map(str::to_owned))
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {} {}@{}: {}", self.seq, self.time, self.user, self.host, self.invocation)?;
+        if !self.undoes.is_empty() {
+            let seqs: Vec<String> = self.undoes.iter().map(|seq| format!("#{}", seq)).collect();
+            write!(f, " (undoes {})", seqs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// The state of the database when it was loaded or last written
+#[derive(Default)]
+pub(super) struct Snapshot {
+    records: HashMap<u64, Record>,
+    curr_id: u64,
+    settings: Settings
+}
+
+impl Database {
+    pub(super) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            records: self.records().map(|r| (r.id(), r)).collect(),
+            curr_id: self.curr_id,
+            settings: self.settings.clone()
+        }
+    }
+
+    // The changes that turn base into this database
+    fn changes_since(&self, base: &Snapshot) -> Vec<Change> {
+        let mut changes = vec![];
+        if base.settings != self.settings {
+            changes.push(Change::Settings { before: base.settings.clone(), after: self.settings.clone() });
+        }
+        let mut seen = HashSet::new();
+        for record in self.records() {
+            seen.insert(record.id());
+            match base.records.get(&record.id()) {
+                None => changes.push(Change::Added(record)),
+                Some(before) if !before.same_as(&record) => changes.push(Change::Modified { before: Box::new(before.clone()), after: Box::new(record) }),
+                Some(_) => ()
+            }
+        }
+        let mut removed: Vec<&Record> = base.records.values().filter(|r| !seen.contains(&r.id())).collect();
+        removed.sort_by_key(|r| r.id());
+        changes.extend(removed.into_iter().cloned().map(Change::Removed));
+        changes
+    }
+
+    /// The entries added, modified and removed between this database and a later version of it
+    pub fn diff(&self, later: &Database) -> Vec<Change> {
+        later.changes_since(&self.snapshot())
+    }
+
+    // Every entry, in the order they are stored
+    fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        self.data_vec.iter().cloned().map(Record::Data)
+            .chain(self.transform_vec.iter().cloned().map(Record::Transform))
+            .chain(self.connection_vec.iter().cloned().map(Record::Connection))
+    }
+
+    pub fn get_log_path(&self) -> PathBuf {
+        Path::new(&self.db_path).with_file_name("log")
+    }
+
+    /// Every event in the operation log, oldest first
+    pub fn read_log(&self) -> Result<Vec<Event>, Box<dyn Error>> {
+        let mut events = Self::read_log_file(&self.get_log_path(), self.format_version)?;
+        self.resolve_events(&mut events, &self.settings.roots);
+        Ok(events)
+    }
+
+    // Reads the events of a log with the given format version, upgrading them in memory (see `migrate.rs`)
+    fn read_log_file(log_path: &Path, version: u32) -> Result<Vec<Event>, Box<dyn Error>> {
+        if !log_path.exists() {
+            return Ok(vec![]);
+        }
+        let mut events = vec![];
+        for (i, line) in fs::read_to_string(log_path)?.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let line = if version < super::FORMAT_VERSION { Self::upgrade_event(line, version)? } else { line.to_owned() };
+            match ron::from_str(&line) {
+                Ok(event) => events.push(event),
+                Err(e) => {
+                    println!("Error: Line {} of {} is not a valid event: {}", i + 1, log_path.to_str().unwrap(), e);
+                    return Err(format!("Line {} of {} is not a valid event: {}", i + 1, log_path.to_str().unwrap(), e).into());
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// The events that touched the entry with the given id and happened after since (a date, see `utils::parse_time`)
+    pub fn log(&self, id: Option<u64>, since: Option<&str>) -> Result<Vec<Event>, Box<dyn Error>> {
+        let since = since.map(utils::parse_time).transpose()?;
+        Ok(self.read_log()?
+            .into_iter()
+            .filter(|e| id.is_none_or(|id| e.changes.iter().any(|c| c.id() == Some(id))))
+            .filter(|e| since.is_none_or(|since| e.get_time().is_some_and(|t| t >= since)))
+            .collect())
+    }
+
+    fn append_event(&self, prev_curr_id: u64, curr_id: u64, mut changes: Vec<Change>, invocation: String, undoes: Vec<u64>, mut moves: Vec<Move>) -> Result<Event, Box<dyn Error>> {
+        let log_path = self.get_log_path();
+        self.make_changes_portable(&mut changes, &mut moves);
+        let seq = match read_last_line(&log_path)? {
+            Some(line) => ron::from_str::<EventSeq>(&line)?.seq + 1,
+            None => 0
+        };
+        let event = Event {
+            seq,
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true),
+            user: whoami::username(),
+            host: whoami::fallible::hostname().unwrap_or_else(|_| "unknown".to_owned()),
+            invocation,
+            prev_curr_id,
+            curr_id,
+            changes,
+            undoes,
+            moves
+        };
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&log_path)?;
+        writeln!(file, "{}", ron::to_string(&event)?)?;
+        Ok(event)
+    }
+
+    // Appends the changes made since the database was loaded (or last written) to the log
+    pub(super) fn journal(&mut self) -> Result<(), Box<dyn Error>> {
+        // The program name without its folder, e.g. `psidb apply -t 1 -d 0`
+        let mut args: Vec<String> = std::env::args().collect();
+        if let Some(name) = args.first().and_then(|a| Path::new(a).file_name()) {
+            args[0] = name.to_str().unwrap().to_owned();
+        }
+        self.journal_as(shell_words::join(args))
+    }
+
+    // Same as `journal`, with the given invocation
+    pub(super) fn journal_as(&mut self, invocation: String) -> Result<(), Box<dyn Error>> {
+        // Databases made before the log existed start it with everything they already have
+        if !self.get_log_path().exists() && !self.journal_base.records.is_empty() {
+            let mut records: Vec<&Record> = self.journal_base.records.values().collect();
+            records.sort_by_key(|r| r.id());
+            let changes = records.into_iter().cloned().map(Change::Added).collect();
+            self.append_event(0, self.journal_base.curr_id, changes, IMPORT.to_owned(), vec![], vec![])?;
+        }
+
+        let base = std::mem::take(&mut self.journal_base);
+        let changes = self.changes_since(&base);
+        let undoes = std::mem::take(&mut self.pending_undoes);
+        let moves = std::mem::take(&mut self.pending_moves);
+        if !changes.is_empty() || base.curr_id != self.curr_id || !undoes.is_empty() || !moves.is_empty() {
+            self.append_event(base.curr_id, self.curr_id, changes, invocation, undoes, moves)?;
+        }
+        self.journal_base = self.snapshot();
+        Ok(())
+    }
+
+    pub(super) fn apply_changes(&mut self, changes: &[Change]) {
+        for change in changes {
+            match change {
+                Change::Added(Record::Data(data)) => self.push_data(data.clone()),
+                Change::Added(Record::Transform(transform)) => self.push_transform(transform.clone()),
+                Change::Added(Record::Connection(connection)) => self.push_connection(connection.clone()),
+                Change::Modified { after, .. } => match after.as_ref() {
+                    Record::Data(data) => self.replace_data(data.clone()),
+                    Record::Transform(transform) => self.replace_transform(transform.clone()),
+                    Record::Connection(connection) => self.replace_connection(connection.clone())
+                },
+                Change::Removed(record) => self.remove_entries(&[record.id()]),
+                Change::Settings { after, .. } => self.settings = after.clone()
+            }
+        }
+    }
+
+    /// Rebuilds the database from its operation log
+    pub fn replay(path_str: Option<&str>) -> Result<Database, Box<dyn Error>> {
+        Self::replay_until(path_str, None)
+    }
+
+    /// A read-only view of the database as it was at the given time (a date, see `utils::parse_time`), rebuilt from
+    /// its operation log
+    pub fn at(path_str: Option<&str>, time: &str) -> Result<Database, Box<dyn Error>> {
+        let time = utils::parse_time(time)?;
+        let mut db = Self::replay_until(path_str, Some(time))?;
+        db.read_only = Some(format!("This is the database as it was at {}, which cannot be changed", time.to_rfc3339_opts(SecondsFormat::Secs, true)));
+        Ok(db)
+    }
+
+    fn replay_until(path_str: Option<&str>, until: Option<DateTime<Utc>>) -> Result<Database, Box<dyn Error>> {
+        let mut db = Database::new_unchecked(&Self::get_psidb_dir(path_str).join("db.ron"));
+        db.local_roots = super::roots::LocalConfig::load()?.roots;
+        let log_path = db.get_log_path();
+        if !log_path.exists() {
+            println!("Error: {} does not exist", log_path.to_str().unwrap());
+            return Err(format!("{} does not exist", log_path.to_str().unwrap()).into());
+        }
+        // The log has the format version of db.ron, and is only upgraded in memory (see `migrate.rs`)
+        let db_path = Path::new(&db.db_path);
+        let version = if db_path.exists() { Self::read_format_version(db_path)? } else { super::FORMAT_VERSION };
+        // Paths are resolved with the roots of the database as it is now (see `roots.rs`)
+        let mut events = Self::read_log_file(&log_path, version)?;
+        let final_roots = events
+            .iter()
+            .rev()
+            .flat_map(|e| e.changes.iter().rev())
+            .find_map(|c| if let Change::Settings { after, .. } = c { Some(after.roots.clone()) } else { None })
+            .unwrap_or_default();
+        db.resolve_events(&mut events, &final_roots);
+
+        // Nothing is known about the database before its first event (for a database older than the log, the first
+        // event is the import of what it had when it was first loaded by a version of psidb with a log)
+        if let (Some(until), Some(first)) = (until, events.first()) {
+            if first.get_time().is_none_or(|t| t > until) {
+                let hint = if first.invocation == IMPORT { ", when the log was started" } else { "" };
+                println!("Error: The history of the database starts at {}{}", first.time, hint);
+                return Err(format!("The history of the database starts at {}{}", first.time, hint).into());
+            }
+        }
+        for event in events {
+            if until.is_some_and(|until| event.get_time().is_none_or(|t| t > until)) {
+                break;
+            }
+            db.apply_changes(&event.changes);
+            db.curr_id = event.curr_id;
+        }
+        db.journal_base = db.snapshot();
+        Ok(db)
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::error::Error;
+use std::collections::HashMap;
+use super::Database;
+use super::entry::{data::Data, connection::Connection, metadata::{Metadata, MetaValue}};
+
+pub struct MdConflict {
+    pub id: u64, // The local id of the entry
+    pub key: String,
+    pub ours: MetaValue,
+    pub theirs: MetaValue
+}
+
+impl fmt::Display for MdConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "entry {} has {}={} here but {}={} in the other database", self.id, self.key, self.ours, self.key, self.theirs)
+    }
+}
+
+#[derive(Default)]
+pub struct MergeReport {
+    pub added: Vec<(u64, u64)>, // (id in the other database, new local id) for the entries that were imported
+    pub matched: Vec<(u64, u64)>, // (id in the other database, local id) for the entries that were already here
+    pub conflicts: Vec<MdConflict>, // Metadata that differs between matched entries (the local value is kept)
+    pub dangling: Vec<u64> // The ids of connections in the other database that refer to entries it does not have
+}
+
+// Adds the metadata keys that are missing locally and reports the ones with a different value
+fn merge_md(id: u64, ours: &mut Metadata, theirs: &Metadata, conflicts: &mut Vec<MdConflict>) {
+    let mut keys: Vec<&String> = theirs.keys().collect();
+    keys.sort();
+    for key in keys {
+        match ours.get(key) {
+            // When the entry was added to each database is not a conflict
+            Some(_) if key == "time" => (),
+            None => {
+                ours.insert(key.clone(), theirs[key].clone());
+            }
+            Some(value) if *value != theirs[key] => conflicts.push(MdConflict { id, key: key.clone(), ours: value.clone(), theirs: theirs[key].clone() }),
+            Some(_) => ()
+        }
+    }
+}
+
+// The sorted hashes of a dataset, which match those of a dataset with the same files at other paths (e.g. on another
+// machine). None if not every file was hashed.
+fn content_key(data: &Data) -> Option<Vec<String>> {
+    let mut hashes = data.hashes.clone();
+    hashes.sort_unstable();
+    (!hashes.is_empty() && hashes.len() == data.paths.len()).then_some(hashes)
+}
+
+impl Database {
+    /// Imports the entries of another database. Data that is already here (same uid, same paths or same contents) and
+    /// transforms that are already here (same uid or same scripts) are not duplicated, and connections are remapped to
+    /// the local ids.
+    pub fn merge(&mut self, other: Database) -> Result<MergeReport, Box<dyn Error>> {
+        let mut report = MergeReport::default();
+        let mut id_map: HashMap<u64, u64> = HashMap::new();
+
+        // Look the local entries up by uid and data by contents
+        let uids: HashMap<String, u64> = self.data_vec.iter().map(|d| (d.uid.clone(), d.id))
+            .chain(self.transform_vec.iter().map(|t| (t.uid.clone(), t.id)))
+            .chain(self.connection_vec.iter().map(|c| (c.uid.clone(), c.id)))
+            .collect();
+        let contents: HashMap<Vec<String>, u64> = self.data_vec.iter().filter_map(|d| Some((content_key(d)?, d.id))).collect();
+
+        for mut data in other.data_vec {
+            let other_id = data.id;
+            let local_id = uids.get(&data.uid).copied().filter(|id| self.get_data(*id).is_some())
+                .or_else(|| self.find_data(&data))
+                .or_else(|| contents.get(&content_key(&data)?).copied());
+            match local_id {
+                Some(local_id) => {
+                    merge_md(local_id, &mut self.get_data_mut(local_id).unwrap().md, &data.md, &mut report.conflicts);
+                    id_map.insert(other_id, local_id);
+                    report.matched.push((other_id, local_id));
+                }
+                None => {
+                    data.id = self.curr_id;
+                    self.curr_id += 1;
+                    id_map.insert(other_id, data.id);
+                    report.added.push((other_id, data.id));
+                    self.push_data(data);
+                }
+            }
+        }
+
+        for mut transform in other.transform_vec {
+            let other_id = transform.id;
+            let local_id = uids.get(&transform.uid).copied().filter(|id| self.get_transform(*id).is_some())
+                .or_else(|| self.find_transform(&transform));
+            match local_id {
+                Some(local_id) => {
+                    merge_md(local_id, &mut self.get_transform_mut(local_id).unwrap().md, &transform.md, &mut report.conflicts);
+                    id_map.insert(other_id, local_id);
+                    report.matched.push((other_id, local_id));
+                }
+                None => {
+                    transform.id = self.curr_id;
+                    self.curr_id += 1;
+                    id_map.insert(other_id, transform.id);
+                    report.added.push((other_id, transform.id));
+                    self.push_transform(transform);
+                }
+            }
+        }
+
+        for connection in other.connection_vec {
+            // Point the connection at the local ids
+            let remap = |ids: &[u64]| ids.iter().map(|id| id_map.get(id).copied()).collect::<Option<Vec<u64>>>();
+            let (Some(in_data_ids), Some(out_data_ids), Some(in_transform_ids), Some(out_transform_ids)) = (
+                remap(&connection.in_data_ids),
+                remap(&connection.out_data_ids),
+                remap(&connection.in_transform_ids),
+                remap(&connection.out_transform_ids)
+            ) else {
+                report.dangling.push(connection.id);
+                continue;
+            };
+            let other_id = connection.id;
+            let connection = Connection { id: self.curr_id, in_data_ids, out_data_ids, in_transform_ids, out_transform_ids, ..connection };
+
+            let local_id = uids.get(&connection.uid).copied().filter(|id| self.get_connection(*id).is_some())
+                .or_else(|| self.find_connection(&connection));
+            match local_id {
+                Some(local_id) => {
+                    merge_md(local_id, &mut self.get_connection_mut(local_id).unwrap().md, &connection.md, &mut report.conflicts);
+                    report.matched.push((other_id, local_id));
+                }
+                None => {
+                    let id = self.try_add_connection(connection)?;
+                    report.added.push((other_id, id));
+                }
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use super::{Database, FORMAT_VERSION};
+use super::entry::metadata::MetaValue;
+use super::journal::MIGRATE;
+
+/* A database written by an older version of psidb is upgraded when it is loaded, one format version at a time, after
+db.ron and the log are copied to .psidb/backup-v<version>/. Text upgrades (for layouts the current types cannot read)
+rewrite db.ron and each event of the log, entry upgrades change the loaded database. The upgraded database is written
+right away and the changes of the entry upgrades are recorded in the log as a `migrate` event. */
+
+/// How a migration upgrades the database
+#[derive(Clone, Copy)]
+pub enum Upgrade {
+    /// Rewrites db.ron and each event of the log (one RON value per line) before they are read
+    Text { db: fn(&str) -> Result<String, Box<dyn Error>>, event: fn(&str) -> Result<String, Box<dyn Error>> },
+    /// Changes the entries once they are read
+    Entries(fn(&mut Database))
+}
+
+// A database during an upgrade, the text of db.ron or the database read from it
+enum Upgrading {
+    Text(String),
+    Entries(Box<Database>)
+}
+
+/// A step from one format version to the next
+pub struct Migration {
+    pub description: &'static str,
+    pub upgrade: Upgrade
+}
+
+/// MIGRATIONS[i] upgrades databases from format version i to i + 1
+pub const MIGRATIONS: [Migration; 1] = [
+    Migration { description: "Metadata values are typed instead of strings", upgrade: Upgrade::Entries(typed_metadata) }
+];
+
+// Metadata values used to all be strings. Only the strings that are written the same way as their typed value are
+// converted, so that e.g. "007" or "1e3" stay strings.
+fn typed_metadata(db: &mut Database) {
+    let mds = db.data_vec.iter_mut().map(|d| &mut d.md)
+        .chain(db.transform_vec.iter_mut().map(|t| &mut t.md))
+        .chain(db.connection_vec.iter_mut().map(|c| &mut c.md));
+    for md in mds {
+        for value in md.values_mut() {
+            if let MetaValue::String(s) = value {
+                let typed = MetaValue::infer(s);
+                if typed.to_string() == *s {
+                    *value = typed;
+                }
+            }
+        }
+    }
+}
+
+// The part of db.ron that every format version has
+#[derive(Deserialize)]
+#[serde(rename = "Database")]
+struct Header {
+    #[serde(default)]
+    format_version: u32 // Databases from before format versions have none
+}
+
+impl Database {
+    /// The format version of the database stored in the given db.ron file
+    pub fn read_format_version(db_path: &Path) -> Result<u32, Box<dyn Error>> {
+        let header: Header = ron::from_str(&fs::read_to_string(db_path)?)?;
+        if header.format_version > FORMAT_VERSION {
+            println!("Error: {} has format version {}, which is newer than this version of psidb supports ({}). Update psidb.", db_path.to_str().unwrap(), header.format_version, FORMAT_VERSION);
+            return Err(format!("{} has format version {}, which is newer than this version of psidb supports ({})", db_path.to_str().unwrap(), header.format_version, FORMAT_VERSION).into());
+        }
+        Ok(header.format_version)
+    }
+
+    /// The migrations that loading the database stored in the given db.ron file will run
+    pub fn pending_migrations(db_path: &Path) -> Result<&'static [Migration], Box<dyn Error>> {
+        let version = Self::read_format_version(db_path)?;
+        Ok(&MIGRATIONS[version as usize..])
+    }
+
+    /// Where the files of a database are copied before it is upgraded from the given format version
+    pub fn get_backup_dir(db_path: &Path, version: u32) -> PathBuf {
+        db_path.with_file_name(format!("backup-v{}", version))
+    }
+
+    // Runs an upgrade that rewrites text, to the given format version
+    fn upgrade_step(upgrade: fn(&str) -> Result<String, Box<dyn Error>>, text: &str, to_version: usize) -> Result<String, Box<dyn Error>> {
+        upgrade(text).map_err(|e| {
+            println!("Error: Could not upgrade the database to format version {}: {}", to_version, e);
+            format!("Could not upgrade the database to format version {}: {}", to_version, e).into()
+        })
+    }
+
+    // Upgrades an event of a log with the given format version (entry upgrades leave the log as it is)
+    pub(super) fn upgrade_event(line: &str, version: u32) -> Result<String, Box<dyn Error>> {
+        let mut line = line.to_owned();
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            if let Upgrade::Text { event, .. } = migration.upgrade {
+                line = Self::upgrade_step(event, &line, i + 1)?;
+            }
+        }
+        Ok(line)
+    }
+
+    // Reads the database stored in the given db.ron file, which has the given format version, and upgrades it in memory
+    fn read_upgraded(db_path: &Path, version: u32) -> Result<Database, Box<dyn Error>> {
+        Self::read_upgraded_with(db_path, version, &MIGRATIONS)
+    }
+
+    // Runs the given migrations from the given format version one step at a time. The database is read before an entry
+    // upgrade and written back to text before a text upgrade.
+    fn read_upgraded_with(db_path: &Path, version: u32, migrations: &[Migration]) -> Result<Database, Box<dyn Error>> {
+        let mut upgrading = Upgrading::Text(fs::read_to_string(db_path)?);
+        for (i, migration) in migrations.iter().enumerate().skip(version as usize) {
+            upgrading = match (migration.upgrade, upgrading) {
+                (Upgrade::Text { db: upgrade, .. }, Upgrading::Text(text)) => Upgrading::Text(Self::upgrade_step(upgrade, &text, i + 1)?),
+                (Upgrade::Text { db: upgrade, .. }, Upgrading::Entries(mut db)) => {
+                    db.map_all_paths(true);
+                    Upgrading::Text(Self::upgrade_step(upgrade, &ron::to_string(&db)?, i + 1)?)
+                }
+                (Upgrade::Entries(upgrade), upgrading) => {
+                    let mut db = match upgrading {
+                        Upgrading::Text(text) => Box::new(Self::parse(&text, db_path)?),
+                        Upgrading::Entries(db) => db
+                    };
+                    upgrade(&mut db);
+                    Upgrading::Entries(db)
+                }
+            };
+        }
+        match upgrading {
+            Upgrading::Text(text) => Self::parse(&text, db_path),
+            Upgrading::Entries(db) => Ok(*db)
+        }
+    }
+
+    /// Loads the database stored in the given db.ron file without writing anything, e.g. to merge it. A database with
+    /// an older format version is only upgraded in memory, and cannot be written.
+    pub fn read_from(db_path: &Path) -> Result<Database, Box<dyn Error>> {
+        let version = Self::read_format_version(db_path)?;
+        let mut db = Self::read_upgraded(db_path, version)?;
+        if version < FORMAT_VERSION {
+            // The log is read with the format it has on disk (see `read_log`)
+            db.format_version = version;
+            db.read_only = Some(format!("{} has format version {} and was only upgraded in memory, load it to upgrade it", db_path.to_str().unwrap(), version));
+        }
+        Ok(db)
+    }
+
+    // Reads the database stored in the given db.ron file, upgrading it first if it has an older format version
+    pub(super) fn read_migrated(db_path: &Path) -> Result<Database, Box<dyn Error>> {
+        let version = Self::read_format_version(db_path)?;
+        if version == FORMAT_VERSION {
+            return Self::parse(&fs::read_to_string(db_path)?, db_path);
+        }
+
+        let log_path = db_path.with_file_name("log");
+        let backup_dir = Self::get_backup_dir(db_path, version);
+        fs::DirBuilder::new().recursive(true).create(&backup_dir)?;
+        fs::copy(db_path, backup_dir.join("db.ron"))?;
+        if log_path.exists() {
+            fs::copy(&log_path, backup_dir.join("log"))?;
+        }
+
+        // Nothing is written until every step succeeded
+        let mut db = Self::read_upgraded(db_path, version)?;
+        let log = if log_path.exists() {
+            let lines = fs::read_to_string(&log_path)?
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| Self::upgrade_event(l, version))
+                .collect::<Result<Vec<String>, _>>()?;
+            Some(lines.join("\n") + "\n")
+        } else {
+            None
+        };
+        db.format_version = FORMAT_VERSION;
+
+        if let Some(log) = log {
+            fs::write(&log_path, log)?;
+        }
+        db.write_file()?;
+        db.journal_as(MIGRATE.to_owned())?;
+        println!("Upgraded {} from format version {} to {} (backup in {})", db_path.to_str().unwrap(), version, FORMAT_VERSION, backup_dir.to_str().unwrap());
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_curr_id(db: &mut Database) {
+        db.curr_id = 10;
+    }
+
+    fn bump_curr_id(db_str: &str) -> Result<String, Box<dyn Error>> {
+        Ok(db_str.replace("curr_id:10", "curr_id:11"))
+    }
+
+    fn unchanged(event_str: &str) -> Result<String, Box<dyn Error>> {
+        Ok(event_str.to_owned())
+    }
+
+    #[test]
+    fn upgrades_run_in_order() {
+        let dir = std::env::temp_dir().join(format!("psidb-test-{}-migrate-order", std::process::id())).join(".psidb");
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("db.ron");
+        Database::new_unchecked(&db_path).write_file().unwrap();
+
+        // The text upgrade sees what the entry upgrade before it changed
+        let migrations = [
+            Migration { description: "", upgrade: Upgrade::Entries(set_curr_id) },
+            Migration { description: "", upgrade: Upgrade::Text { db: bump_curr_id, event: unchanged } }
+        ];
+        assert_eq!(Database::read_upgraded_with(&db_path, 0, &migrations).unwrap().curr_id, 11);
+        // Only the steps from the given version run
+        assert_eq!(Database::read_upgraded_with(&db_path, 1, &migrations).unwrap().curr_id, 0);
+    }
+}
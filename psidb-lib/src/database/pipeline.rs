@@ -0,0 +1,209 @@
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+use std::cmp::Ordering;
+use std::collections::{HashMap, BTreeMap};
+use serde::Deserialize;
+use super::Database;
+use super::entry::metadata::Metadata;
+
+/* A pipeline file (e.g. psidb.pipeline.ron) looks like
+Pipeline(
+    transforms: {
+        "clean": (scripts: [(path: "scripts/clean.py", args: ["--n", "10"])]),
+    },
+    data: {
+        "raw": Paths(["raw/run1.h5", "raw/run2.h5"]),
+        "calibration": Md({"kind": "calibration"}),
+    },
+    steps: [
+        (name: "cleaned", transform: "clean", inputs: ["raw"]),
+        (name: "calibrated", transform: "calibrate", inputs: ["cleaned", "calibration"], md: {"note": "v2"}),
+    ],
+)
+Relative paths are relative to the folder of the pipeline file. */
+
+#[derive(Deserialize)]
+pub struct Pipeline {
+    #[serde(default)]
+    pub transforms: HashMap<String, PipelineTransform>,
+    #[serde(default)]
+    pub data: BTreeMap<String, DataSelector>, // Sorted so that new data gets its ids in a reproducible order
+    pub steps: Vec<PipelineStep>
+}
+
+#[derive(Deserialize)]
+pub struct PipelineTransform {
+    pub scripts: Vec<PipelineScript>,
+    #[serde(default)]
+    pub md: Metadata
+}
+
+#[derive(Deserialize)]
+pub struct PipelineScript {
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>
+}
+
+#[derive(Deserialize)]
+pub enum DataSelector {
+    Id(u64), // The data with this id
+    Paths(Vec<String>), // The data with exactly these paths (added to the database if needed)
+    Md(Metadata) // Every dataset whose metadata contains all these key/value pairs
+}
+
+#[derive(Deserialize)]
+pub struct PipelineStep {
+    pub name: String,
+    pub transform: String,
+    pub inputs: Vec<String>, // Names of data selectors or of earlier steps
+    #[serde(default)]
+    pub md: Metadata,
+    #[serde(default)]
+    pub out_dir_as_cwd: bool
+}
+
+pub struct StepResult {
+    pub name: String,
+    pub data_id: u64,
+    pub connection_id: u64,
+    pub cache_hit: bool
+}
+
+impl Pipeline {
+    pub fn load(path: &Path) -> Result<Pipeline, Box<dyn Error>> {
+        let pipeline_str = fs::read_to_string(path)?;
+        let pipeline = ron::from_str(&pipeline_str)?;
+        Ok(pipeline)
+    }
+
+    /// The indices of the steps in an order where every step comes after the steps it consumes
+    fn step_order(&self) -> Result<Vec<usize>, Box<dyn Error>> {
+        let step_idx: HashMap<&str, usize> = self.steps.iter().enumerate().map(|(i, s)| (s.name.as_str(), i)).collect();
+        if step_idx.len() != self.steps.len() {
+            println!("Error: Step names must be unique");
+            return Err("Step names must be unique".into());
+        }
+
+        // Make sure every name refers to something
+        for step in &self.steps {
+            if self.data.contains_key(&step.name) {
+                println!("Error: Step {} has the same name as a data selector", step.name);
+                return Err(format!("Step {} has the same name as a data selector", step.name).into());
+            }
+            if !self.transforms.contains_key(&step.transform) {
+                println!("Error: Step {} uses the unknown transform {}", step.name, step.transform);
+                return Err(format!("Step {} uses the unknown transform {}", step.name, step.transform).into());
+            }
+            for input in &step.inputs {
+                if !self.data.contains_key(input) && !step_idx.contains_key(input.as_str()) {
+                    println!("Error: Step {} uses the unknown input {}", step.name, input);
+                    return Err(format!("Step {} uses the unknown input {}", step.name, input).into());
+                }
+            }
+        }
+
+        // Repeatedly take the steps whose inputs are all done
+        let mut order = Vec::with_capacity(self.steps.len());
+        let mut done = vec![false; self.steps.len()];
+        while order.len() < self.steps.len() {
+            let ready: Vec<usize> = (0..self.steps.len())
+                .filter(|i| !done[*i] && self.steps[*i].inputs.iter().all(|input| step_idx.get(input.as_str()).is_none_or(|j| done[*j])))
+                .collect();
+            if ready.is_empty() {
+                println!("Error: The steps of the pipeline form a cycle");
+                return Err("The steps of the pipeline form a cycle".into());
+            }
+            for i in ready {
+                done[i] = true;
+                order.push(i);
+            }
+        }
+        Ok(order)
+    }
+}
+
+impl Database {
+    fn select_data(&mut self, selector: &DataSelector, base_dir: &Path) -> Result<Vec<u64>, Box<dyn Error>> {
+        match selector {
+            DataSelector::Id(id) => {
+                if self.get_data(*id).is_none() {
+                    println!("Error: Data with id {} does not exist", id);
+                    return Err(format!("Data with id {} does not exist", id).into());
+                }
+                Ok(vec![*id])
+            }
+            DataSelector::Paths(paths) => {
+                let paths: Vec<String> = paths.iter().map(|p| base_dir.join(p).to_str().unwrap().to_owned()).collect();
+                let data = self.make_data(&paths, HashMap::new())?;
+                match self.find_data(&data) {
+                    Some(id) => Ok(vec![id]),
+                    // New data is added like `add_data` adds it
+                    None => Ok(vec![self.add_new_data(data, None)?])
+                }
+            }
+            DataSelector::Md(md) => {
+                let ids: Vec<u64> = self.data_vec
+                    .iter()
+                    .filter(|d| md.iter().all(|(k, v)| d.md.get(k).and_then(|dv| dv.compare(v)) == Some(Ordering::Equal)))
+                    .map(|d| d.id)
+                    .collect();
+                if ids.is_empty() {
+                    println!("Error: No data matches the metadata {:?}", md);
+                    return Err(format!("No data matches the metadata {:?}", md).into());
+                }
+                Ok(ids)
+            }
+        }
+    }
+
+    fn pipeline_transform(&mut self, transform: &PipelineTransform, base_dir: &Path) -> Result<u64, Box<dyn Error>> {
+        let script_paths: Vec<String> = transform.scripts.iter().map(|s| base_dir.join(&s.path).to_str().unwrap().to_owned()).collect();
+        let script_args: Vec<Vec<String>> = transform.scripts.iter().map(|s| s.args.clone()).collect();
+        let mut transform = self.make_transform(&script_paths, Some(&script_args), None, transform.md.clone())?;
+
+        // Reuse the transform if it is already in the database, new transforms follow the transform template
+        match self.find_transform(&transform) {
+            Some(id) => Ok(id),
+            None => {
+                self.apply_template(&mut transform.md, self.settings.transform_template.as_ref())?;
+                self.try_add_transform(transform)
+            }
+        }
+    }
+
+    /// Registers the transforms and data of a pipeline file and applies each of its steps. Steps that were already
+    /// computed are reused, so running the same pipeline again only computes what changed.
+    pub fn run_pipeline(&mut self, pipeline_path: &Path) -> Result<Vec<StepResult>, Box<dyn Error>> {
+        let pipeline = Pipeline::load(pipeline_path)?;
+        let base_dir = pipeline_path.canonicalize()?.parent().unwrap().to_path_buf();
+        let order = pipeline.step_order()?;
+
+        // The data ids that each data selector and step name stands for
+        let mut outputs: HashMap<&str, Vec<u64>> = HashMap::new();
+        for (name, selector) in &pipeline.data {
+            outputs.insert(name, self.select_data(selector, &base_dir)?);
+        }
+
+        let mut transform_ids = HashMap::new();
+        let mut results = Vec::with_capacity(order.len());
+        for i in order {
+            let step = &pipeline.steps[i];
+            let transform_id = match transform_ids.get(&step.transform) {
+                Some(id) => *id,
+                None => {
+                    let id = self.pipeline_transform(&pipeline.transforms[&step.transform], &base_dir)?;
+                    transform_ids.insert(&step.transform, id);
+                    id
+                }
+            };
+
+            let data_ids: Vec<u64> = step.inputs.iter().flat_map(|input| outputs[input.as_str()].clone()).collect();
+            let (data_id, connection_id, cache_hit) = self.apply_with_md(transform_id, &data_ids, step.md.clone(), step.out_dir_as_cwd, false)?;
+            outputs.insert(&step.name, vec![data_id]);
+            results.push(StepResult { name: step.name.clone(), data_id, connection_id, cache_hit });
+        }
+        Ok(results)
+    }
+}
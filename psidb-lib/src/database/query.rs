@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::cmp::Ordering;
+use std::path::Path;
+use super::Database;
+use super::entry::metadata::{Metadata, MetaValue, TYPE_NAMES};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Op {
+    Exists,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+/// A condition on one metadata key, written `key<op>value` with op one of = != < <= > >=, or just `key` to require
+/// the key. The value is typed like in `key=value` metadata, e.g. `n:int=5`.
+pub struct Filter {
+    pub key: String,
+    pub op: Op,
+    pub value: Option<MetaValue>
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Filter, Box<dyn Error>> {
+        let Some(start) = s.find(['=', '!', '<', '>']) else {
+            return Ok(Filter { key: s.trim().to_owned(), op: Op::Exists, value: None });
+        };
+        let ops = [("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt)];
+        let Some((op_str, op)) = ops.into_iter().find(|(op_str, _)| s[start..].starts_with(op_str)) else {
+            println!("Error: Could not parse the condition {}", s);
+            return Err(format!("Could not parse the condition {}", s).into());
+        };
+
+        let key = s[..start].trim();
+        let value = s[start + op_str.len()..].trim();
+        let (key, value) = match key.rsplit_once(':') {
+            Some((key, type_name)) if TYPE_NAMES.contains(&type_name) => (key, MetaValue::parse_as(value, type_name)?),
+            _ => (key, MetaValue::infer(value))
+        };
+        if key.is_empty() {
+            println!("Error: The condition {} has no key", s);
+            return Err(format!("The condition {} has no key", s).into());
+        }
+        Ok(Filter { key: key.to_owned(), op, value: Some(value) })
+    }
+
+    pub fn matches(&self, md: &Metadata) -> bool {
+        let Some(value) = md.get(&self.key) else {
+            return false;
+        };
+        let Some(expected) = &self.value else {
+            return true;
+        };
+        // Values of incompatible types are only ever different
+        match (self.op, value.compare(expected)) {
+            (Op::Ne, ordering) => ordering != Some(Ordering::Equal),
+            (_, None) => false,
+            (Op::Eq, Some(ordering)) => ordering == Ordering::Equal,
+            (Op::Lt, Some(ordering)) => ordering == Ordering::Less,
+            (Op::Le, Some(ordering)) => ordering != Ordering::Greater,
+            (Op::Gt, Some(ordering)) => ordering == Ordering::Greater,
+            (Op::Ge, Some(ordering)) => ordering != Ordering::Less,
+            (Op::Exists, _) => true
+        }
+    }
+}
+
+impl Database {
+    /// The ids of the entries whose metadata matches all the conditions (see `Filter`), sorted by the value of the
+    /// metadata key sort_key (`-key` sorts in descending order) with the entries without that key last
+    pub fn query<T: AsRef<str>>(&self, conditions: &[T], sort_key: Option<&str>) -> Result<Vec<u64>, Box<dyn Error>> {
+        let filters = conditions.iter().map(|c| Filter::parse(c.as_ref())).collect::<Result<Vec<Filter>, _>>()?;
+        let mds = self.data_vec.iter().map(|d| (d.id, &d.md))
+            .chain(self.transform_vec.iter().map(|t| (t.id, &t.md)))
+            .chain(self.connection_vec.iter().map(|c| (c.id, &c.md)));
+        let mut matches: Vec<(u64, &Metadata)> = mds.filter(|(_, md)| filters.iter().all(|f| f.matches(md))).collect();
+
+        if let Some(sort_key) = sort_key {
+            let (key, descending) = match sort_key.strip_prefix('-') {
+                Some(key) => (key, true),
+                None => (sort_key, false)
+            };
+            matches.sort_by(|(_, a), (_, b)| match (a.get(key), b.get(key)) {
+                (Some(a), Some(b)) if descending => b.sort_cmp(a),
+                (Some(a), Some(b)) => a.sort_cmp(b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal
+            });
+        }
+        Ok(matches.into_iter().map(|(id, _)| id).collect())
+    }
+
+    /// The datasets that track the given path, either as one of their paths or as a file inside one of their
+    /// directories, with the path of the data that holds it
+    pub fn which(&self, path: &str) -> Result<Vec<(u64, String)>, Box<dyn Error>> {
+        // Missing files can still be in the manifest of a directory
+        let path = self.resolve_path(path);
+        let path = match super::store::canonicalize(Path::new(&path)) {
+            Ok(path) => path,
+            Err(_) => std::path::absolute(&path)?
+        };
+        let mut found = vec![];
+        for data in &self.data_vec {
+            if let Some(data_path) = data.paths.iter().find(|p| Path::new(p) == path) {
+                found.push((data.id, data_path.clone()));
+            } else if let Some((dir, _)) = data.tracking_dir(&path) {
+                found.push((data.id, dir.to_owned()));
+            }
+        }
+        Ok(found)
+    }
+}
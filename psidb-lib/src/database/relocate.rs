@@ -0,0 +1,323 @@
+use std::fs;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use walkdir::WalkDir;
+use super::Database;
+use super::entry::data::Data;
+use super::journal::Move;
+use crate::utils;
+
+#[derive(Default)]
+pub struct MoveReport {
+    pub src: String, // The absolute paths of the move
+    pub dst: String,
+    pub rewritten: Vec<u64>, // The datasets and transforms whose paths were under the source
+    pub relocated: Vec<(u64, String, String)>, // Paths that were not at their new place but were found by content hash
+    pub missing: Vec<(u64, String)> // Paths that are still missing after the move
+}
+
+/// A new place for a missing path of a dataset
+pub struct Relocation {
+    pub data_id: u64,
+    pub old_path: String,
+    pub new_path: String,
+    pub candidates: usize // How many files had the same contents (the one with the same name is preferred)
+}
+
+#[derive(Default)]
+pub struct RelocationSearch {
+    pub found: Vec<Relocation>,
+    pub not_found: Vec<(u64, String)>, // Missing files that are not in the searched folders
+    pub skipped: Vec<(u64, String)>, // Missing directories, which cannot be found by their contents
+    pub scanned: usize, // The number of files looked at
+    pub hashed: usize // The number of files whose contents had to be read (the others were in the hash cache)
+}
+
+// (inode, size, modification time in seconds and nanoseconds) of a file
+type FileKey = (u64, u64, u64, u32);
+
+/// The content hashes of the files that were scanned, so that scanning them again does not read them. A file is
+/// known by its inode, size and modification time, so that changing or replacing it invalidates its hash.
+#[derive(Serialize, Deserialize, Default)]
+pub struct HashCache {
+    hashes: HashMap<FileKey, String>
+}
+
+impl HashCache {
+    pub fn load(path: &Path) -> Result<HashCache, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(HashCache::default());
+        }
+        // A broken cache is only slower
+        Ok(ron::from_str(&fs::read_to_string(path)?).unwrap_or_default())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn key(metadata: &fs::Metadata) -> Result<FileKey, Box<dyn Error>> {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok((inode, metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+    }
+
+    /// The content hash of a file, and whether it had to be read
+    pub fn hash(&mut self, path: &Path, metadata: &fs::Metadata) -> Result<(String, bool), Box<dyn Error>> {
+        let key = Self::key(metadata)?;
+        if let Some(hash) = self.hashes.get(&key) {
+            return Ok((hash.clone(), false));
+        }
+        let hash = utils::hash_file(path)?;
+        self.hashes.insert(key, hash.clone());
+        Ok((hash, true))
+    }
+
+    /// The hash of a set of paths like `utils::hash_paths`, reading only the files that are not in the cache
+    pub fn hash_paths<T: AsRef<Path>>(&mut self, paths: &[T]) -> Result<String, Box<dyn Error>> {
+        utils::hash_paths_with(paths, &mut |file| Ok(self.hash(file, &fs::metadata(file)?)?.0))
+    }
+}
+
+// Moves a file or directory, copying it when it goes to another file system
+pub(super) fn move_on_disk(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    match fs::rename(src, dst) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            copy_recursive(src, dst)?;
+            if src.is_dir() { fs::remove_dir_all(src)? } else { fs::remove_file(src)? }
+        }
+        result => result?
+    }
+    // The symlinks into the store are relative to where they were
+    super::store::follow_moved_links(src, dst)
+}
+
+fn copy_recursive(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    if fs::symlink_metadata(src)?.file_type().is_symlink() {
+        super::store::symlink(&fs::read_link(src)?, dst)?;
+    } else if src.is_dir() {
+        fs::DirBuilder::new().recursive(true).create(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(src, dst)?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Moves a file or directory on disk (into dst if it is a directory, like `mv`), and rewrites the paths of the
+    /// datasets and transforms under it. With db_only, the move (possibly into dst) was already done by another tool
+    /// and only the database changes. Registered files that are not where the move puts them are looked for by content
+    /// hash under the destination. Nothing changes on disk if the database cannot follow, and nothing is written.
+    pub fn move_path(&mut self, src: &str, dst: &str, db_only: bool) -> Result<MoveReport, Box<dyn Error>> {
+        // Registered paths are canonical, so the source and the folder of the destination are made canonical too
+        let src = std::path::absolute(self.resolve_path(src))?;
+        let src = super::store::canonicalize(&src).unwrap_or(src);
+        let mut dst = std::path::absolute(self.resolve_path(dst))?;
+        if db_only {
+            // The source was moved into the destination folder if it is there now (`mv a.csv out/`)
+            let name = src.file_name().ok_or("Cannot move the root folder")?;
+            if dst.is_dir() && !src.exists() && dst.join(name).exists() {
+                dst = dst.join(name);
+            }
+        } else {
+            if !src.exists() {
+                println!("Error: {} does not exist", src.to_str().unwrap());
+                return Err(format!("{} does not exist", src.to_str().unwrap()).into());
+            }
+            if dst.is_dir() {
+                dst = dst.join(src.file_name().ok_or("Cannot move the root folder")?);
+            }
+            if dst.exists() {
+                println!("Error: {} already exists", dst.to_str().unwrap());
+                return Err(format!("{} already exists", dst.to_str().unwrap()).into());
+            }
+        }
+        if let Some(parent) = dst.parent().and_then(|p| p.canonicalize().ok()) {
+            dst = parent.join(dst.file_name().unwrap_or_default());
+        }
+        if dst.starts_with(&src) {
+            println!("Error: Cannot move {} into itself", src.to_str().unwrap());
+            return Err(format!("Cannot move {} into itself", src.to_str().unwrap()).into());
+        }
+
+        if !db_only {
+            move_on_disk(&src, &dst)?;
+        }
+        // Put everything back if the database cannot follow the move
+        let backup = (self.data_vec.clone(), self.transform_vec.clone());
+        let report = self.follow_move(&src, &dst);
+        if report.is_err() {
+            (self.data_vec, self.transform_vec) = backup;
+            self.reindex();
+            if !db_only {
+                move_on_disk(&dst, &src)?;
+            }
+        } else if !db_only {
+            // Undoing the operation moves it back
+            self.pending_moves.push(Move { src: src.to_str().unwrap().to_owned(), dst: dst.to_str().unwrap().to_owned() });
+        }
+        report
+    }
+
+    // Rewrites the paths of the entries under src after it moved to dst
+    fn follow_move(&mut self, src: &Path, dst: &Path) -> Result<MoveReport, Box<dyn Error>> {
+        let (src_str, dst_str) = (src.to_str().unwrap().to_owned(), dst.to_str().unwrap().to_owned());
+        let mut report = MoveReport { rewritten: self.remap_paths(&src_str, &dst_str)?, src: src_str, dst: dst_str, ..MoveReport::default() };
+
+        // Files that are not where the move put them may have been renamed too
+        let mut hashes: Option<HashMap<String, Vec<PathBuf>>> = None;
+        for &id in &report.rewritten {
+            let Some(data) = self.get_data(id) else { continue };
+            let mut new_data = data.clone();
+            for (path, hash) in new_data.paths.iter_mut().zip(&data.hashes) {
+                if Path::new(path).exists() || !Path::new(path).starts_with(dst) {
+                    continue;
+                }
+                let hashes = match &mut hashes {
+                    Some(hashes) => hashes,
+                    None => hashes.insert(hash_files(dst)?)
+                };
+                match hashes.get(hash).and_then(|candidates| candidates.first()) {
+                    Some(found) => {
+                        let found = found.to_str().unwrap().to_owned();
+                        report.relocated.push((id, path.clone(), found.clone()));
+                        *path = found;
+                    }
+                    None => report.missing.push((id, path.clone()))
+                }
+            }
+            if new_data.paths != data.paths {
+                if let Some(other) = self.find_data(&new_data).filter(|other| *other != id) {
+                    println!("Error: Data {} would have the same paths as data {} after the move", id, other);
+                    return Err(format!("Data {} would have the same paths as data {} after the move", id, other).into());
+                }
+                self.replace_data(new_data);
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn get_hash_cache_path(&self) -> PathBuf {
+        Path::new(&self.db_path).with_file_name("hash_cache")
+    }
+
+    /// Looks for the missing files of datasets in the given folders, by size and content hash, and returns where they
+    /// are. Nothing changes until the relocations are given to `relocate`.
+    pub fn find_relocations<T: AsRef<str>>(&self, search_dirs: &[T]) -> Result<RelocationSearch, Box<dyn Error>> {
+        let mut search = RelocationSearch::default();
+        let mut missing = vec![]; // (data id, path, hash, size)
+        for data in &self.data_vec {
+            for (i, (path, hash)) in data.paths.iter().zip(&data.hashes).enumerate() {
+                if Path::new(path).exists() {
+                    continue;
+                }
+                if data.trees.iter().any(|t| t.root == *path) {
+                    search.skipped.push((data.id, path.clone()));
+                } else {
+                    missing.push((data.id, path, hash, data.sizes.get(i).copied()));
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(search);
+        }
+
+        // Only files with the size of a missing file need to be hashed (unless the size of one is unknown)
+        let sizes: Option<HashSet<u64>> = missing.iter().map(|(_, _, _, size)| *size).collect();
+        let cache_path = self.get_hash_cache_path();
+        let mut cache = HashCache::load(&cache_path)?;
+        let mut candidates: HashMap<&str, Vec<PathBuf>> = HashMap::new();
+        let wanted: HashSet<&str> = missing.iter().map(|(_, _, hash, _)| hash.as_str()).collect();
+        for dir in search_dirs {
+            let dir = self.resolve_path(dir.as_ref());
+            if !Path::new(&dir).is_dir() {
+                println!("Error: {} is not a directory", dir);
+                return Err(format!("{} is not a directory", dir).into());
+            }
+            for entry in WalkDir::new(&dir).sort_by_file_name() {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                search.scanned += 1;
+                let metadata = entry.metadata()?;
+                if sizes.as_ref().is_some_and(|sizes| !sizes.contains(&metadata.len())) {
+                    continue;
+                }
+                let (hash, read) = cache.hash(entry.path(), &metadata)?;
+                search.hashed += read as usize;
+                if let Some(hash) = wanted.get(hash.as_str()) {
+                    candidates.entry(hash).or_default().push(entry.path().canonicalize()?);
+                }
+            }
+        }
+        cache.save(&cache_path)?;
+
+        for (data_id, path, hash, _) in missing {
+            let Some(found) = candidates.get(hash.as_str()) else {
+                search.not_found.push((data_id, path.clone()));
+                continue;
+            };
+            let name = Path::new(path).file_name();
+            let new_path = found.iter().find(|p| p.file_name() == name).unwrap_or(&found[0]);
+            search.found.push(Relocation {
+                data_id,
+                old_path: path.clone(),
+                new_path: new_path.to_str().unwrap().to_owned(),
+                candidates: found.len()
+            });
+        }
+        Ok(search)
+    }
+
+    /// Moves the missing paths of datasets to where `find_relocations` found them
+    pub fn relocate(&mut self, relocations: &[Relocation]) -> Result<(), Box<dyn Error>> {
+        let mut all_data: Vec<Data> = vec![];
+        for relocation in relocations {
+            let Some(data) = self.get_data(relocation.data_id) else {
+                println!("Error: Data with id {} does not exist", relocation.data_id);
+                return Err(format!("Data with id {} does not exist", relocation.data_id).into());
+            };
+            let position = all_data.iter().position(|d| d.id == data.id).unwrap_or_else(|| {
+                all_data.push(data.clone());
+                all_data.len() - 1
+            });
+            for path in all_data[position].paths.iter_mut().filter(|p| **p == relocation.old_path) {
+                *path = relocation.new_path.clone();
+            }
+        }
+
+        for data in &all_data {
+            if let Some(other) = self.find_data(data).filter(|other| *other != data.id) {
+                println!("Error: Data {} would have the same paths as data {}", data.id, other);
+                return Err(format!("Data {} would have the same paths as data {}", data.id, other).into());
+            }
+        }
+        for data in all_data {
+            self.replace_data(data);
+        }
+        Ok(())
+    }
+
+}
+
+// The files under path (or path itself) by content hash
+fn hash_files(path: &Path) -> Result<HashMap<String, Vec<PathBuf>>, Box<dyn Error>> {
+    let files = if path.is_dir() { utils::list_files(path)? } else if path.exists() { vec![path.to_path_buf()] } else { vec![] };
+    let mut hashes: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for file in files {
+        hashes.entry(utils::hash_file(&file)?).or_default().push(file);
+    }
+    Ok(hashes)
+}
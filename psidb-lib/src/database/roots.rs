@@ -0,0 +1,301 @@
+use std::fs;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use super::Database;
+use super::journal::{Change, Record, Event, Move};
+use super::entry::{data::Data, transform::Transform};
+
+/* Paths under a named root are stored (in db.ron and in the log) as `$name/relative/path`, and resolved with the
+location of the root on this machine when the database is loaded. The database says where each root is, and the local
+config of a machine can override it, e.g. to open a database copied from a cluster on a laptop. */
+
+const ROOT_PREFIX: char = '$';
+
+/// The settings of psidb on this machine, in ~/.config/psidb/config.toml (or $XDG_CONFIG_HOME/psidb/config.toml)
+#[derive(Serialize, Deserialize, Default)]
+pub struct LocalConfig {
+    #[serde(default)]
+    pub roots: BTreeMap<String, String> // Where roots are on this machine, overriding the locations in databases
+}
+
+impl LocalConfig {
+    pub fn path() -> PathBuf {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| home::home_dir().unwrap_or_default().join(".config"));
+        config_dir.join("psidb").join("config.toml")
+    }
+
+    pub fn load() -> Result<LocalConfig, Box<dyn Error>> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(LocalConfig::default());
+        }
+        toml::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+            println!("Error: Could not parse {}: {}", path.to_str().unwrap(), e);
+            format!("Could not parse {}: {}", path.to_str().unwrap(), e).into()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = Self::path();
+        fs::DirBuilder::new().recursive(true).create(path.parent().unwrap())?;
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// A named root of a database
+pub struct Root {
+    pub name: String,
+    pub path: String, // Where the database says it is
+    pub local_path: Option<String> // Where the local config says it is on this machine
+}
+
+impl Root {
+    /// Where the root is on this machine
+    pub fn location(&self) -> &str {
+        self.local_path.as_deref().unwrap_or(&self.path)
+    }
+}
+
+// An absolute version of a path given by the user, without a trailing slash
+fn absolute_path(path: &str) -> Result<String, Box<dyn Error>> {
+    let path = super::store::canonicalize(Path::new(path)).or_else(|_| std::path::absolute(path))?;
+    Ok(path.components().collect::<PathBuf>().to_str().unwrap().to_owned())
+}
+
+fn map_data_paths(data: &mut Data, f: &impl Fn(&str) -> String) {
+    for path in &mut data.paths {
+        *path = f(path);
+    }
+    for sidecar in &mut data.sidecars {
+        sidecar.path = f(&sidecar.path);
+    }
+    for tree in &mut data.trees {
+        tree.root = f(&tree.root);
+    }
+}
+
+fn map_transform_paths(transform: &mut Transform, f: &impl Fn(&str) -> String) {
+    for path in &mut transform.script_paths {
+        *path = f(path);
+    }
+}
+
+fn map_record_paths(record: &mut Record, f: &impl Fn(&str) -> String) {
+    match record {
+        Record::Data(data) => map_data_paths(data, f),
+        Record::Transform(transform) => map_transform_paths(transform, f),
+        Record::Connection(..) => ()
+    }
+}
+
+fn map_change_paths(change: &mut Change, f: &impl Fn(&str) -> String) {
+    match change {
+        Change::Added(record) | Change::Removed(record) => map_record_paths(record, f),
+        Change::Modified { before, after } => {
+            map_record_paths(before, f);
+            map_record_paths(after, f);
+        }
+        Change::Settings { .. } => ()
+    }
+}
+
+// The name and location on this machine of each root
+fn root_locations(roots: &BTreeMap<String, String>, local_roots: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    roots
+        .iter()
+        .map(|(name, path)| (name.clone(), local_roots.get(name).unwrap_or(path).trim_end_matches('/').to_owned()))
+        .map(|(name, location)| (name, if location.is_empty() { "/".to_owned() } else { location }))
+        .collect()
+}
+
+fn resolve_path(locations: &[(String, String)], path: &str) -> String {
+    let Some(rest) = path.strip_prefix(ROOT_PREFIX) else {
+        return path.to_owned();
+    };
+    let (name, relative) = rest.split_once('/').unwrap_or((rest, ""));
+    match locations.iter().find(|(n, _)| n == name) {
+        Some((_, location)) if relative.is_empty() => location.clone(),
+        Some((_, location)) => Path::new(location).join(relative).to_str().unwrap().to_owned(),
+        None => path.to_owned()
+    }
+}
+
+fn portable_path(locations: &[(String, String)], path: &str) -> String {
+    let innermost = locations
+        .iter()
+        .filter_map(|(name, location)| Some((location.len(), name, Path::new(path).strip_prefix(location).ok()?)))
+        .max_by_key(|(len, _, _)| *len);
+    match innermost {
+        Some((_, name, relative)) if relative.as_os_str().is_empty() => format!("{}{}", ROOT_PREFIX, name),
+        Some((_, name, relative)) => format!("{}{}/{}", ROOT_PREFIX, name, relative.to_str().unwrap()),
+        None => path.to_owned()
+    }
+}
+
+impl Database {
+    /// The roots of the database, with their location on this machine
+    pub fn get_roots(&self) -> Vec<Root> {
+        self.settings.roots
+            .iter()
+            .map(|(name, path)| Root { name: name.clone(), path: path.clone(), local_path: self.local_roots.get(name).cloned() })
+            .collect()
+    }
+
+    // The name and location on this machine of each root
+    fn root_locations(&self) -> Vec<(String, String)> {
+        root_locations(&self.settings.roots, &self.local_roots)
+    }
+
+    /// The path on this machine of a path relative to a root (`$name/relative/path`), other paths are unchanged
+    pub fn resolve_path(&self, path: &str) -> String {
+        resolve_path(&self.root_locations(), path)
+    }
+
+    /// The path relative to the innermost root that contains it (`$name/relative/path`), or the path itself if no
+    /// root does
+    pub fn portable_path(&self, path: &str) -> String {
+        portable_path(&self.root_locations(), path)
+    }
+
+    // Turns every path of the entries into a path relative to a root (before writing) or back (after loading). The
+    // index must be rebuilt afterwards.
+    pub(super) fn map_all_paths(&mut self, portable: bool) {
+        if self.settings.roots.is_empty() {
+            return;
+        }
+        let locations = self.root_locations();
+        let f = |path: &str| if portable { portable_path(&locations, path) } else { resolve_path(&locations, path) };
+        for data in &mut self.data_vec {
+            map_data_paths(data, &f);
+        }
+        for transform in &mut self.transform_vec {
+            map_transform_paths(transform, &f);
+        }
+    }
+
+    // Makes the paths of the changes and moves of a new event of the log relative to the roots
+    pub(super) fn make_changes_portable(&self, changes: &mut [Change], moves: &mut [Move]) {
+        let locations = self.root_locations();
+        for change in changes {
+            map_change_paths(change, &|path| portable_path(&locations, path));
+        }
+        for m in moves {
+            m.src = portable_path(&locations, &m.src);
+            m.dst = portable_path(&locations, &m.dst);
+        }
+    }
+
+    // Resolves the paths of events read from the log with the roots the database had after each event (an event is
+    // written with those), or with final_roots for roots it did not have yet
+    pub(super) fn resolve_events(&self, events: &mut [Event], final_roots: &BTreeMap<String, String>) {
+        let mut roots = BTreeMap::new();
+        for event in events {
+            if let Some(settings) = event.changes.iter().rev().find_map(|c| if let Change::Settings { after, .. } = c { Some(after) } else { None }) {
+                roots = settings.roots.clone();
+            }
+            let mut all_roots = final_roots.clone();
+            all_roots.extend(roots.clone());
+            let locations = root_locations(&all_roots, &self.local_roots);
+            for change in &mut event.changes {
+                map_change_paths(change, &|path| resolve_path(&locations, path));
+            }
+            for m in &mut event.moves {
+                m.src = resolve_path(&locations, &m.src);
+                m.dst = resolve_path(&locations, &m.dst);
+            }
+        }
+    }
+
+    /// Adds a root (or moves the root with the same name). Paths under it are stored relative to it from then on.
+    /// With local, only sets where the root is on this machine (in the local config).
+    pub fn set_root(&mut self, name: &str, path: &str, local: bool) -> Result<(), Box<dyn Error>> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            println!("Error: Invalid root name {}, use letters, digits, _ and -", name);
+            return Err(format!("Invalid root name {}, use letters, digits, _ and -", name).into());
+        }
+        let path = absolute_path(path)?;
+
+        // The entries under the root follow it
+        self.map_all_paths(true);
+        if local {
+            let mut config = LocalConfig::load()?;
+            config.roots.insert(name.to_owned(), path);
+            config.save()?;
+            self.local_roots = config.roots;
+        } else {
+            self.settings.roots.insert(name.to_owned(), path);
+        }
+        self.map_all_paths(false);
+        self.reindex();
+        Ok(())
+    }
+
+    /// Removes a root, paths under it are stored as absolute paths from then on. With local, only removes the location
+    /// of the root on this machine.
+    pub fn remove_root(&mut self, name: &str, local: bool) -> Result<(), Box<dyn Error>> {
+        if !local {
+            if self.settings.roots.remove(name).is_none() {
+                println!("Error: Root {} does not exist", name);
+                return Err(format!("Root {} does not exist", name).into());
+            }
+            return Ok(());
+        }
+
+        let mut config = LocalConfig::load()?;
+        if config.roots.remove(name).is_none() {
+            println!("Error: Root {} has no local location", name);
+            return Err(format!("Root {} has no local location", name).into());
+        }
+        config.save()?;
+        self.map_all_paths(true);
+        self.local_roots = config.roots;
+        self.map_all_paths(false);
+        self.reindex();
+        Ok(())
+    }
+
+    /// Replaces the prefix old by new in every path of the datasets and transforms, and returns the ids of the
+    /// entries that changed
+    pub fn remap_paths(&mut self, old: &str, new: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+        let (old, new) = (absolute_path(old)?, absolute_path(new)?);
+        let f = |path: &str| match Path::new(path).strip_prefix(&old) {
+            Ok(relative) if relative.as_os_str().is_empty() => new.clone(),
+            Ok(relative) => Path::new(&new).join(relative).to_str().unwrap().to_owned(),
+            Err(_) => path.to_owned()
+        };
+
+        let mut changed = vec![];
+        let mut all_data = vec![];
+        for data in &self.data_vec {
+            let mut new_data = data.clone();
+            map_data_paths(&mut new_data, &f);
+            if new_data.paths != data.paths || new_data.sidecars != data.sidecars || new_data.trees != data.trees {
+                all_data.push(new_data);
+            }
+        }
+        for data in all_data {
+            changed.push(data.id);
+            self.replace_data(data);
+        }
+        let mut transforms = vec![];
+        for transform in &self.transform_vec {
+            let mut new_transform = transform.clone();
+            map_transform_paths(&mut new_transform, &f);
+            if new_transform.script_paths != transform.script_paths {
+                transforms.push(new_transform);
+            }
+        }
+        for transform in transforms {
+            changed.push(transform.id);
+            self.replace_transform(transform);
+        }
+        changed.sort_unstable();
+        Ok(changed)
+    }
+}
@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use super::template::Template;
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct Settings {
+    pub data_root: Option<String>, // Where the per-run output directories of transforms go, defaults to .psidb/data/
+    #[serde(default)]
+    pub data_template: Option<String>, // The template new data follows unless its metadata names another one
+    #[serde(default)]
+    pub transform_template: Option<String>, // Same for new transforms
+    #[serde(default)]
+    pub templates: Vec<Template>,
+    #[serde(default)]
+    pub sidecars: Vec<String>, // Patterns of the metadata files next to data files, e.g. `{}.json` or `{stem}.meta.yaml`
+    #[serde(default)]
+    pub sidecar_prefix: Option<String>, // Prepended to the keys read from sidecars
+    #[serde(default)]
+    pub extractors: Vec<String>, // The metadata extractors that run on new data, e.g. `stat` or `mime`
+    #[serde(default)]
+    pub roots: BTreeMap<String, String>, // The named roots that paths are stored relative to (see `roots.rs`)
+    #[serde(default)]
+    pub store: bool // Whether the files of new data (added, imported, or made by apply and update) are moved into the object store (see `store.rs`)
+}
+
+// The items of a comma-separated list
+fn split_list(value: Option<&str>) -> Vec<String> {
+    value.map_or(vec![], |v| v.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_owned).collect())
+}
+
+impl Settings {
+    pub const KEYS: [&'static str; 7] = ["data_root", "data_template", "transform_template", "sidecars", "sidecar_prefix", "extractors", "store"];
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match key {
+            "data_root" => Ok(self.data_root.clone()),
+            "data_template" => Ok(self.data_template.clone()),
+            "transform_template" => Ok(self.transform_template.clone()),
+            "sidecars" => Ok(Some(self.sidecars.join(",")).filter(|s| !s.is_empty())),
+            "sidecar_prefix" => Ok(self.sidecar_prefix.clone()),
+            "extractors" => Ok(Some(self.extractors.join(",")).filter(|s| !s.is_empty())),
+            "store" => Ok(self.store.then(|| "true".to_owned())),
+            _ => Err(Self::unknown_key(key))
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        match key {
+            "data_root" => self.data_root = value.map(str::to_owned),
+            "data_template" | "transform_template" => {
+                if let Some(name) = value.filter(|name| !self.templates.iter().any(|t| t.name == *name)) {
+                    println!("Error: Template {} does not exist", name);
+                    return Err(format!("Template {} does not exist", name).into());
+                }
+                if key == "data_template" {
+                    self.data_template = value.map(str::to_owned);
+                } else {
+                    self.transform_template = value.map(str::to_owned);
+                }
+            }
+            "sidecars" => self.sidecars = split_list(value),
+            "sidecar_prefix" => self.sidecar_prefix = value.map(str::to_owned),
+            "extractors" => self.extractors = split_list(value),
+            "store" => match value {
+                None | Some("false") => self.store = false,
+                Some("true") => self.store = true,
+                Some(value) => {
+                    println!("Error: Invalid value {} for store, expected true or false", value);
+                    return Err(format!("Invalid value {} for store, expected true or false", value).into());
+                }
+            },
+            _ => return Err(Self::unknown_key(key))
+        }
+        Ok(())
+    }
+
+    fn unknown_key(key: &str) -> Box<dyn Error> {
+        println!("Error: Unknown setting {}, expected one of {}", key, Self::KEYS.join(", "));
+        format!("Unknown setting {}, expected one of {}", key, Self::KEYS.join(", ")).into()
+    }
+}
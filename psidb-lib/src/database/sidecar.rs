@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use super::Database;
+use super::entry::data::{Data, Sidecar};
+use super::entry::metadata::parser;
+use crate::utils;
+
+// The file next to path that the pattern names, where {} stands for the file name and {stem} for the file name
+// without its last extension (e.g. `{}.json` gives foo.dat.json and `{stem}.meta.yaml` gives foo.meta.yaml)
+fn sidecar_path(path: &Path, pattern: &str) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let stem = path.file_stem()?.to_str()?;
+    Some(path.with_file_name(pattern.replace("{stem}", stem).replace("{}", name)))
+}
+
+impl Database {
+    /// Adds the metadata of the sidecars of the data (found with the patterns of the settings) to its metadata and
+    /// records them. Keys the data already has are kept, so metadata given explicitly wins over sidecars.
+    pub(super) fn read_sidecars(&self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        let prefix = self.settings.sidecar_prefix.as_deref().unwrap_or("");
+        for path in &data.paths {
+            for pattern in &self.settings.sidecars {
+                let Some(sidecar_path) = sidecar_path(Path::new(path), pattern).filter(|p| p.is_file()) else {
+                    continue;
+                };
+                let sidecar_path = sidecar_path.canonicalize()?.to_str().unwrap().to_owned();
+                if data.paths.contains(&sidecar_path) || data.sidecars.iter().any(|s| s.path == sidecar_path) {
+                    continue;
+                }
+
+                let mut keys = vec![];
+                for (key, value) in parser::read_file(&sidecar_path)? {
+                    let key = format!("{}{}", prefix, key);
+                    if !data.md.contains_key(&key) {
+                        data.md.insert(key.clone(), value);
+                        keys.push(key);
+                    }
+                }
+                keys.sort_unstable();
+                let hash = utils::hash_path(Path::new(&sidecar_path))?;
+                data.sidecars.push(Sidecar { path: sidecar_path, hash, keys });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the paths of the data that are sidecars of its other paths (e.g. when a transform writes both)
+    pub(super) fn remove_sidecar_paths(&self, data: &mut Data) {
+        let sidecar_paths: Vec<PathBuf> = data.paths
+            .iter()
+            .flat_map(|path| self.settings.sidecars.iter().filter_map(|pattern| sidecar_path(Path::new(path), pattern).filter(|p| p != Path::new(path))))
+            .collect();
+        data.paths.retain(|path| !sidecar_paths.iter().any(|p| p == Path::new(path)));
+    }
+
+    /// Reads the sidecars of the data with the given id again, replacing the metadata that came from them, and returns
+    /// their paths. Sidecars that no longer exist are dropped and new ones are picked up.
+    pub fn refresh_md(&mut self, id: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        let Some(data) = self.get_data(id) else {
+            println!("Error: Data with id {} does not exist", id);
+            return Err(format!("Data with id {} does not exist", id).into());
+        };
+
+        let mut data = data.clone();
+        for sidecar in std::mem::take(&mut data.sidecars) {
+            for key in sidecar.keys {
+                data.md.remove(&key);
+            }
+        }
+        self.read_sidecars(&mut data)?;
+
+        let sidecar_paths = data.sidecars.iter().map(|s| s.path.clone()).collect();
+        *self.get_data_mut(id).unwrap() = data;
+        Ok(sidecar_paths)
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::collections::HashMap;
+use std::error::Error;
+use super::Database;
+use super::entry::action::Action;
+use super::entry::tree::FileChange;
+use super::relocate::HashCache;
+
+#[derive(Clone)]
+pub enum StaleReason {
+    FilesChanged { data_id: u64, paths: Vec<String> }, // The files of a dataset changed since it was registered
+    ScriptChanged { transform_id: u64, script_paths: Vec<String> }, // Scripts of a transform have newer commits
+    Deprecated { id: u64, reason: String } // An entry was deprecated
+}
+
+impl fmt::Display for StaleReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StaleReason::FilesChanged { data_id, paths } => write!(f, "the files of data {} changed ({})", data_id, paths.join(", ")),
+            StaleReason::ScriptChanged { transform_id, script_paths } => write!(f, "the scripts of transform {} have newer commits ({})", transform_id, script_paths.join(", ")),
+            StaleReason::Deprecated { id, reason } if reason.is_empty() => write!(f, "entry {} is deprecated", id),
+            StaleReason::Deprecated { id, reason } => write!(f, "entry {} is deprecated: {}", id, reason)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Stale {
+    pub id: u64,
+    pub reason: StaleReason,
+    pub chain: Vec<u64> // The ids from the entry that caused the staleness down to this entry
+}
+
+impl fmt::Display for Stale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let chain: Vec<String> = self.chain.iter().map(u64::to_string).collect();
+        write!(f, "{} is stale because {} (via {})", self.id, self.reason, chain.join(" -> "))
+    }
+}
+
+pub struct ChangedData {
+    pub data_id: u64,
+    pub paths: Vec<String>, // The paths that are missing or whose contents changed
+    pub sidecars: Vec<String>, // Same for the sidecars its metadata was read from
+    pub files: Vec<(String, FileChange)> // The files added, removed or modified inside its changed directories
+}
+
+impl Database {
+    pub fn deprecate(&mut self, id: u64, reason: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let md = if self.get_data(id).is_some() {
+            &mut self.get_data_mut(id).unwrap().md
+        } else if self.get_transform(id).is_some() {
+            &mut self.get_transform_mut(id).unwrap().md
+        } else {
+            println!("Error: Data or transform with id {} does not exist", id);
+            return Err(format!("Data or transform with id {} does not exist", id).into());
+        };
+        md.insert("deprecated".to_owned(), reason.unwrap_or_default().into());
+        Ok(())
+    }
+
+    /// The paths and sidecars of each dataset (all datasets if data_ids is None) that are missing or changed since they were registered
+    pub fn verify(&self, data_ids: Option<&[u64]>) -> Result<Vec<ChangedData>, Box<dyn Error>> {
+        let mut changed = vec![];
+        for data in &self.data_vec {
+            if data_ids.is_some_and(|ids| !ids.contains(&data.id)) {
+                continue;
+            }
+            let paths = data.changed_paths();
+            let sidecars = data.changed_sidecars();
+            if !paths.is_empty() || !sidecars.is_empty() {
+                let files = data.changed_files(&paths);
+                changed.push(ChangedData { data_id: data.id, paths, sidecars, files });
+            }
+        }
+
+        // Make sure all the requested ids were datasets
+        for id in data_ids.unwrap_or_default() {
+            if self.get_data(*id).is_none() {
+                println!("Error: Data with id {} does not exist", id);
+                return Err(format!("Data with id {} does not exist", id).into());
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Every dataset and transform that is out of date, together with the reason and the chain of ids that caused it
+    pub fn status(&self) -> Vec<Stale> {
+        // The entries that are stale on their own (with the chain of ids that made them stale)
+        let mut causes = HashMap::new();
+        for data in &self.data_vec {
+            let paths = data.changed_paths();
+            if !paths.is_empty() {
+                causes.insert(data.id, (StaleReason::FilesChanged { data_id: data.id, paths }, vec![data.id]));
+            }
+        }
+
+        // Data made by `apply` from inputs whose contents changed since (e.g. inputs re-hashed by `update`)
+        let mut current_hashes = HashMap::new();
+        // Files that did not change since they were last hashed are not read again
+        let cache_path = self.get_hash_cache_path();
+        let mut cache = HashCache::load(&cache_path).unwrap_or_default();
+        for connection in self.connection_vec.iter().filter(|c| c.action == Action::Apply && c.in_data_hashes.len() == c.in_data_ids.len()) {
+            for (in_id, hash) in connection.in_data_ids.iter().zip(&connection.in_data_hashes) {
+                let Some(in_data) = self.get_data(*in_id) else { continue };
+                let current = current_hashes.entry(*in_id).or_insert_with(|| cache.hash_paths(&in_data.paths).ok());
+                if current.as_ref() != Some(hash) {
+                    for out_id in &connection.out_data_ids {
+                        let reason = StaleReason::FilesChanged { data_id: *in_id, paths: in_data.paths.clone() };
+                        causes.entry(*out_id).or_insert((reason, vec![*in_id, *out_id]));
+                    }
+                    break;
+                }
+            }
+        }
+        // A cache that cannot be saved only makes the next status slower
+        let _ = cache.save(&cache_path);
+
+        for transform in &self.transform_vec {
+            let script_paths = transform.outdated_scripts();
+            if !script_paths.is_empty() {
+                causes.insert(transform.id, (StaleReason::ScriptChanged { transform_id: transform.id, script_paths }, vec![transform.id]));
+            }
+        }
+        let deprecated = self.data_vec.iter().map(|d| (d.id, &d.md)).chain(self.transform_vec.iter().map(|t| (t.id, &t.md)));
+        for (id, md) in deprecated {
+            if let Some(reason) = md.get("deprecated") {
+                causes.entry(id).or_insert_with(|| (StaleReason::Deprecated { id, reason: reason.to_string() }, vec![id]));
+            }
+        }
+
+        // Propagate the causes down the provenance graph
+        let mut memo = HashMap::new();
+        let ids: Vec<u64> = self.data_vec.iter().map(|d| d.id).chain(self.transform_vec.iter().map(|t| t.id)).collect();
+        let mut stale: Vec<Stale> = ids
+            .iter()
+            .filter_map(|id| self.stale_entry(*id, &causes, &mut memo))
+            .filter(|s| self.newer_versions(s.id).is_empty()) // Entries replaced by `update` are not reported
+            .collect();
+        stale.sort_by_key(|s| s.id);
+        stale
+    }
+
+    fn stale_entry(&self, id: u64, causes: &HashMap<u64, (StaleReason, Vec<u64>)>, memo: &mut HashMap<u64, Option<Stale>>) -> Option<Stale> {
+        if let Some(stale) = memo.get(&id) {
+            return stale.clone();
+        }
+        memo.insert(id, None); // Guards against cycles made with `connect`
+
+        let stale = if let Some((reason, chain)) = causes.get(&id) {
+            Some(Stale { id, reason: reason.clone(), chain: chain.clone() })
+        } else {
+            // A newer version does not inherit the staleness of the version it replaces
+            let parents: Vec<u64> = self.in_connections(id).iter().filter(|c| c.action != Action::Update).flat_map(|c| c.in_ids()).collect();
+            parents
+                .into_iter()
+                .find_map(|parent| self.stale_entry(parent, causes, memo))
+                .map(|parent| {
+                    let mut chain = parent.chain;
+                    chain.push(id);
+                    Stale { id, reason: parent.reason, chain }
+                })
+        };
+        memo.insert(id, stale.clone());
+        stale
+    }
+}
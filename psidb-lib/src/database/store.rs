@@ -0,0 +1,210 @@
+use std::fs;
+use std::io;
+use std::error::Error;
+use std::path::{Path, PathBuf, Component};
+use walkdir::WalkDir;
+use super::Database;
+
+/* With the `store` setting (or `add-data --store`), the files of new datasets are moved into .psidb/objects/<hash>,
+named by their content hash so that datasets with the same files share them, and made read-only. The registered paths
+become symlinks to the objects, so that the data stays where the user put it but cannot be overwritten or deleted by
+accident. The symlinks are relative, so that they still work when the folder with the data and the database moves.
+`checkout` copies a dataset out of the store. */
+
+// The object that a symlink at link points to, if it points into a store. link can be where the symlink was before
+// it moved, since the target is relative to the folder of the symlink (e.g. ../../objects/<hash> for the outputs of
+// apply).
+fn linked_object(link: &Path, target: &Path) -> Option<PathBuf> {
+    let object = normalize(&link.parent().unwrap_or_else(|| Path::new("")).join(target));
+    object.parent().is_some_and(|dir| dir.ends_with(".psidb/objects")).then_some(object)
+}
+
+// Whether path is a symlink to an object of a store
+fn is_stored(path: &Path) -> bool {
+    let is_link = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    is_link && fs::read_link(path).is_ok_and(|target| linked_object(path, &target).is_some())
+}
+
+/// The canonical form of a path, like `Path::canonicalize`, except that symlinks into an object store are kept (they
+/// stand for the files that were stored)
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    let path = &std::path::absolute(path)?;
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if is_stored(path) => Ok(parent.canonicalize().unwrap_or_else(|_| parent.to_path_buf()).join(name)),
+        _ => path.canonicalize()
+    }
+}
+
+// The path from dir to target, both canonical
+fn relative_path(target: &Path, dir: &Path) -> PathBuf {
+    let common = target.components().zip(dir.components()).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return target.to_path_buf(); // E.g. on another drive
+    }
+    dir.components().skip(common).map(|_| Component::ParentDir).chain(target.components().skip(common)).collect()
+}
+
+// Removes the `..` of a path without looking at the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => (),
+            component => normalized.push(component)
+        }
+    }
+    normalized
+}
+
+// Replaces path with a symlink to object, relative to the folder of path. The symlink replaces it in one step, so
+// path is never missing.
+fn link_to_object(path: &Path, object: &Path) -> Result<(), Box<dyn Error>> {
+    let name = path.file_name().ok_or("Cannot store the root folder")?;
+    let dir = path.parent().ok_or("Cannot store the root folder")?.canonicalize()?;
+    let link = path.with_file_name(format!(".{}.psidb-link", name.to_str().unwrap()));
+    symlink(&relative_path(object, &dir), &link)?;
+    if let Err(e) = fs::rename(&link, path) {
+        fs::remove_file(&link)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Points the store symlinks under dst, which was moved there from src, at their objects again
+pub(super) fn follow_moved_links(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    for entry in WalkDir::new(dst).follow_root_links(false) {
+        let entry = entry?;
+        if !entry.path_is_symlink() {
+            continue;
+        }
+        let link = entry.into_path();
+        let target = fs::read_link(&link)?;
+        let old_link = if link == dst { src.to_path_buf() } else { src.join(link.strip_prefix(dst)?) };
+        if let Some(object) = linked_object(&old_link, &target).filter(|_| target.is_relative()) {
+            link_to_object(&link, &object)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(super) fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub(super) fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+impl Database {
+    pub fn get_objects_dir(&self) -> PathBuf {
+        Path::new(&self.db_path).with_file_name("objects")
+    }
+
+    // Moves a file with the given content hash into the store (unless the store already has the contents) and puts
+    // a symlink to the object in its place
+    fn store_file(&self, path: &Path, hash: &str) -> Result<(), Box<dyn Error>> {
+        if is_stored(path) {
+            return Ok(());
+        }
+        let objects_dir = self.get_objects_dir();
+        fs::DirBuilder::new().recursive(true).create(&objects_dir)?;
+        let object = objects_dir.canonicalize()?.join(hash);
+        if !object.exists() {
+            // A hard link is free, a copy is needed on another file system
+            let tmp = object.with_extension("tmp");
+            if fs::hard_link(path, &tmp).is_err() {
+                fs::copy(path, &tmp)?;
+            }
+            let mut permissions = fs::metadata(&tmp)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(&tmp, permissions)?;
+            fs::rename(&tmp, &object)?;
+        }
+        link_to_object(path, &object)
+    }
+
+    /// Moves the files of a dataset into the object store (the tracked files of its directories), and puts symlinks
+    /// to them in their place
+    pub fn store_data(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
+        let Some(data) = self.get_data(id) else {
+            println!("Error: Data with id {} does not exist", id);
+            return Err(format!("Data with id {} does not exist", id).into());
+        };
+        let changed = data.changed_paths();
+        if !changed.is_empty() {
+            println!("Error: {} changed since data {} was added, see `psidb verify`", changed[0], id);
+            return Err(format!("{} changed since data {} was added", changed[0], id).into());
+        }
+
+        for (path, hash) in data.paths.iter().zip(&data.hashes) {
+            match data.trees.iter().find(|t| t.root == *path) {
+                Some(tree) => {
+                    for file in &tree.files {
+                        self.store_file(&Path::new(path).join(&file.path), &file.hash)?;
+                    }
+                }
+                None => self.store_file(Path::new(path), hash)?
+            }
+        }
+        let mut data = data.clone();
+        data.stored = true;
+        self.replace_data(data);
+        Ok(())
+    }
+
+    /// Copies the files of a dataset into dir, from the object store (or from where they are if the dataset is not
+    /// stored and they did not change), and returns the paths of the copies
+    pub fn checkout(&self, id: u64, dir: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let Some(data) = self.get_data(id) else {
+            println!("Error: Data with id {} does not exist", id);
+            return Err(format!("Data with id {} does not exist", id).into());
+        };
+        let dir = Path::new(dir);
+        fs::DirBuilder::new().recursive(true).create(dir)?;
+
+        // Every file to copy, as (where the contents are, where the copy goes)
+        let objects_dir = self.get_objects_dir();
+        let source = |path: PathBuf, hash: &str| -> Result<PathBuf, Box<dyn Error>> {
+            let object = objects_dir.join(hash);
+            if object.exists() {
+                Ok(object)
+            } else if path.is_file() && crate::utils::hash_path(&path)? == hash {
+                Ok(path)
+            } else {
+                println!("Error: The contents of {} are not in the store and the file is missing or changed", path.to_str().unwrap());
+                Err(format!("The contents of {} are not in the store and the file is missing or changed", path.to_str().unwrap()).into())
+            }
+        };
+        let mut targets: Vec<PathBuf> = vec![];
+        let mut copies = vec![];
+        for (path, hash) in data.paths.iter().zip(&data.hashes) {
+            let target = dir.join(Path::new(path).file_name().ok_or("Cannot check out the root folder")?);
+            if target.exists() || targets.contains(&target) {
+                println!("Error: {} already exists", target.to_str().unwrap());
+                return Err(format!("{} already exists", target.to_str().unwrap()).into());
+            }
+            match data.trees.iter().find(|t| t.root == *path) {
+                Some(tree) => {
+                    for file in &tree.files {
+                        copies.push((source(Path::new(path).join(&file.path), &file.hash)?, target.join(&file.path)));
+                    }
+                    fs::DirBuilder::new().recursive(true).create(&target)?;
+                }
+                None => copies.push((source(PathBuf::from(path), hash)?, target.clone()))
+            }
+            targets.push(target);
+        }
+
+        // The copies are new files, writable unlike the objects
+        for (from, to) in &copies {
+            fs::DirBuilder::new().recursive(true).create(to.parent().unwrap())?;
+            io::copy(&mut fs::File::open(from)?, &mut fs::File::create(to)?)?;
+        }
+        Ok(targets.iter().map(|t| t.to_str().unwrap().to_owned()).collect())
+    }
+}
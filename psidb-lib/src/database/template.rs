@@ -0,0 +1,187 @@
+use std::fs;
+use std::fmt;
+use std::error::Error;
+use std::cmp::Ordering;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use super::Database;
+use super::entry::metadata::{Metadata, MetaValue, TYPE_NAMES};
+
+// The metadata key that names the template an entry follows
+pub const TEMPLATE_KEY: &str = "template";
+
+/// A named set of metadata fields that entries must fill in
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Template {
+    pub name: String,
+    pub fields: Vec<TemplateField>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TemplateField {
+    pub key: String,
+    #[serde(rename = "type", default)]
+    pub type_name: Option<String>, // One of TYPE_NAMES, any type if None
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub allowed: Vec<MetaValue>, // Any value if empty
+    #[serde(default)]
+    pub regex: Option<String>, // Must match the whole value
+    #[serde(default)]
+    pub default: Option<MetaValue>
+}
+
+impl Template {
+    /// Reads a template from a RON file
+    pub fn load(path: &str) -> Result<Template, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        match ron::from_str(&contents) {
+            Ok(template) => Ok(template),
+            Err(e) => {
+                println!("Error: Could not parse template {}: {}", path, e);
+                Err(format!("Could not parse template {}: {}", path, e).into())
+            }
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        for (i, field) in self.fields.iter().enumerate() {
+            if self.fields[..i].iter().any(|f| f.key == field.key) {
+                return Err(format!("the key {} appears twice", field.key));
+            }
+            if let Some(type_name) = &field.type_name {
+                if !TYPE_NAMES.contains(&type_name.as_str()) {
+                    return Err(format!("{} has the unknown type {}, expected one of {}", field.key, type_name, TYPE_NAMES.join(", ")));
+                }
+            }
+            if let Some(re) = &field.regex {
+                Regex::new(re).map_err(|e| format!("{} has an invalid regex: {}", field.key, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in the defaults and converts the values to the types of the template, or lists everything md gets wrong
+    pub fn validate(&self, md: &mut Metadata) -> Result<(), Box<dyn Error>> {
+        let mut problems = vec![];
+        for field in &self.fields {
+            let value = match (md.get(&field.key), &field.default) {
+                (Some(value), _) => value.clone(),
+                (None, Some(default)) => default.clone(),
+                (None, None) => {
+                    if field.required {
+                        problems.push(format!("{} is required", field.key));
+                    }
+                    continue;
+                }
+            };
+
+            // Values typed in as strings (or integers for floats) are converted
+            let value = match &field.type_name {
+                Some(type_name) if value.type_name() != type_name => match MetaValue::try_parse_as(&value.to_string(), type_name) {
+                    Some(value) => value,
+                    None => {
+                        problems.push(format!("{} must be a {}, not {}", field.key, type_name, value));
+                        continue;
+                    }
+                },
+                _ => value
+            };
+            if !field.allowed.is_empty() && !field.allowed.iter().any(|a| a.compare(&value) == Some(Ordering::Equal)) {
+                let allowed: Vec<String> = field.allowed.iter().map(MetaValue::to_string).collect();
+                problems.push(format!("{} must be one of {}, not {}", field.key, allowed.join(", "), value));
+                continue;
+            }
+            if let Some(re) = &field.regex {
+                let re = Regex::new(&format!("^(?:{})$", re))?;
+                if !re.is_match(&value.to_string()) {
+                    problems.push(format!("{} must match {}, not {}", field.key, field.regex.as_ref().unwrap(), value));
+                    continue;
+                }
+            }
+            md.insert(field.key.clone(), value);
+        }
+
+        if !problems.is_empty() {
+            println!("Error: The metadata does not follow the template {}: {}", self.name, problems.join("; "));
+            return Err(format!("The metadata does not follow the template {}: {}", self.name, problems.join("; ")).into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TemplateField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.type_name.as_deref().unwrap_or("any"))?;
+        if self.required {
+            write!(f, " (required)")?;
+        }
+        if !self.allowed.is_empty() {
+            let allowed: Vec<String> = self.allowed.iter().map(MetaValue::to_string).collect();
+            write!(f, " one of {}", allowed.join(", "))?;
+        }
+        if let Some(re) = &self.regex {
+            write!(f, " matching {}", re)?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " default {}", default)?;
+        }
+        Ok(())
+    }
+}
+
+impl Database {
+    pub fn get_templates(&self) -> &[Template] {
+        &self.settings.templates
+    }
+
+    pub fn get_template(&self, name: &str) -> Option<&Template> {
+        self.settings.templates.iter().find(|t| t.name == name)
+    }
+
+    /// Adds a template, or replaces the one with the same name
+    pub fn set_template(&mut self, template: Template) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = template.check() {
+            println!("Error: Invalid template {}: {}", template.name, e);
+            return Err(format!("Invalid template {}: {}", template.name, e).into());
+        }
+        match self.settings.templates.iter_mut().find(|t| t.name == template.name) {
+            Some(existing) => *existing = template,
+            None => self.settings.templates.push(template)
+        }
+        Ok(())
+    }
+
+    /// Removes a template. Entries that follow it keep their metadata.
+    pub fn remove_template(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        if self.get_template(name).is_none() {
+            println!("Error: Template {} does not exist", name);
+            return Err(format!("Template {} does not exist", name).into());
+        }
+        if self.settings.data_template.as_deref() == Some(name) || self.settings.transform_template.as_deref() == Some(name) {
+            println!("Error: Template {} is a default template, unset data_template/transform_template first", name);
+            return Err(format!("Template {} is a default template", name).into());
+        }
+        self.settings.templates.retain(|t| t.name != name);
+        Ok(())
+    }
+
+    // Validates md against the template it names, or the default template of its kind
+    pub(super) fn apply_template(&self, md: &mut Metadata, default: Option<&String>) -> Result<(), Box<dyn Error>> {
+        let name = match md.get(TEMPLATE_KEY) {
+            Some(name) => name.to_string(),
+            None => match default {
+                Some(name) => name.clone(),
+                None => return Ok(())
+            }
+        };
+        let Some(template) = self.get_template(&name) else {
+            println!("Error: Template {} does not exist", name);
+            return Err(format!("Template {} does not exist", name).into());
+        };
+        template.validate(md)?;
+        md.insert(TEMPLATE_KEY.to_owned(), MetaValue::String(name));
+        Ok(())
+    }
+}
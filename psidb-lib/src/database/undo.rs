@@ -0,0 +1,147 @@
+use std::fs;
+use std::error::Error;
+use std::path::Path;
+use std::collections::HashSet;
+use super::Database;
+use super::journal::{Event, Change, Record, Move, IMPORT, MIGRATE};
+use super::relocate::move_on_disk;
+
+impl Database {
+    fn get_record(&self, id: u64) -> Option<Record> {
+        if let Some(data) = self.get_data(id) {
+            Some(Record::Data(data.clone()))
+        } else if let Some(transform) = self.get_transform(id) {
+            Some(Record::Transform(transform.clone()))
+        } else {
+            self.get_connection(id).map(|c| Record::Connection(c.clone()))
+        }
+    }
+
+    // The change that reverts change, or why it cannot be reverted (the entry changed since)
+    fn invert_change(&self, change: &Change) -> Result<Change, String> {
+        match change {
+            Change::Added(record) => match self.get_record(record.id()) {
+                Some(current) if current.same_as(record) => Ok(Change::Removed(current)),
+                Some(_) => Err(format!("{} {} was modified afterwards", record.kind(), record.id())),
+                None => Err(format!("{} {} was removed afterwards", record.kind(), record.id()))
+            },
+            Change::Modified { before, after } => match self.get_record(after.id()) {
+                Some(current) if current.same_as(after) => Ok(Change::Modified { before: Box::new(current), after: before.clone() }),
+                Some(_) => Err(format!("{} {} was modified afterwards", after.kind(), after.id())),
+                None => Err(format!("{} {} was removed afterwards", after.kind(), after.id()))
+            },
+            Change::Removed(record) => match self.get_record(record.id()) {
+                None => Ok(Change::Added(record.clone())),
+                Some(current) => Err(format!("the id {} is used by {} {} now", record.id(), current.kind(), current.id()))
+            },
+            Change::Settings { before, after } if *after == self.settings => Ok(Change::Settings { before: after.clone(), after: before.clone() }),
+            Change::Settings { .. } => Err("the settings were changed afterwards".to_owned())
+        }
+    }
+
+    // Moves back a file or folder that an operation moved, unless something took its old place or the data files
+    // that were moved with it changed since
+    fn move_back(event: &Event, m: &Move) -> Result<(), String> {
+        if !Path::new(&m.dst).exists() {
+            return Err(format!("{} is not there anymore", m.dst));
+        }
+        if Path::new(&m.src).exists() {
+            return Err(format!("{} exists again", m.src));
+        }
+        for change in &event.changes {
+            if let Change::Modified { after, .. } = change {
+                if let Record::Data(data) = after.as_ref() {
+                    if let Some(path) = data.changed_paths().into_iter().find(|p| Path::new(p).starts_with(&m.dst)) {
+                        return Err(format!("{} changed since it was moved", path));
+                    }
+                }
+            }
+        }
+        if let Some(parent) = Path::new(&m.src).parent() {
+            fs::DirBuilder::new().recursive(true).create(parent).map_err(|e| e.to_string())?;
+        }
+        move_on_disk(Path::new(&m.dst), Path::new(&m.src)).map_err(|e| e.to_string())
+    }
+
+    // A connection that refers to an entry that does not exist, and that entry
+    fn find_dangling_connection(&self) -> Option<(u64, u64)> {
+        self.connection_vec.iter().find_map(|c| {
+            let missing = c.in_data_ids.iter().chain(&c.out_data_ids).find(|id| self.get_data(**id).is_none())
+                .or_else(|| c.in_transform_ids.iter().chain(&c.out_transform_ids).find(|id| self.get_transform(**id).is_none()));
+            missing.map(|id| (c.id, *id))
+        })
+    }
+
+    /// Reverts the last n operations in the log that were not undone already, and returns them. Nothing changes if
+    /// one of them cannot be reverted (e.g. a later operation uses an entry it added).
+    pub fn undo(&mut self, n: usize) -> Result<Vec<Event>, Box<dyn Error>> {
+        let events = self.read_log()?;
+        let undone: HashSet<u64> = events.iter().flat_map(|e| e.undoes.iter().copied()).collect();
+        let targets: Vec<Event> = events
+            .into_iter()
+            .rev()
+            .filter(|e| e.undoes.is_empty() && e.invocation != IMPORT && e.invocation != MIGRATE && !undone.contains(&e.seq))
+            .take(n)
+            .collect();
+        if targets.len() < n {
+            println!("Error: Only {} operations can be undone", targets.len());
+            return Err(format!("Only {} operations can be undone", targets.len()).into());
+        }
+
+        // Keep the current state to restore it if an operation cannot be reverted
+        let backup = (self.data_vec.clone(), self.transform_vec.clone(), self.connection_vec.clone(), self.settings.clone());
+        let mut error = None;
+        'events: for event in &targets {
+            for change in event.changes.iter().rev() {
+                match self.invert_change(change) {
+                    Ok(inverse) => self.apply_changes(&[inverse]),
+                    Err(e) => {
+                        error = Some(format!("Cannot undo #{} ({}) because {}", event.seq, event.invocation, e));
+                        break 'events;
+                    }
+                }
+            }
+        }
+        if error.is_none() {
+            if let Some((connection_id, id)) = self.find_dangling_connection() {
+                error = Some(format!("Cannot undo because connection {} uses entry {}, which the undo removes. Remove connection {} first", connection_id, id, connection_id));
+            }
+        }
+
+        // Files and folders that the operations moved go back where they were
+        let mut moved_back: Vec<Move> = vec![];
+        if error.is_none() {
+            'moves: for event in &targets {
+                for m in event.moves.iter().rev() {
+                    if let Err(e) = Self::move_back(event, m) {
+                        error = Some(format!("Cannot undo #{} ({}) because {}", event.seq, event.invocation, e));
+                        break 'moves;
+                    }
+                    moved_back.push(Move { src: m.dst.clone(), dst: m.src.clone() });
+                }
+            }
+        }
+        if let Some(error) = error {
+            for m in moved_back.iter().rev() {
+                move_on_disk(Path::new(&m.dst), Path::new(&m.src))?;
+            }
+            (self.data_vec, self.transform_vec, self.connection_vec, self.settings) = backup;
+            self.reindex();
+            println!("Error: {}", error);
+            return Err(error.into());
+        }
+
+        // Give the ids back when nothing uses them anymore (not even a leftover output directory)
+        for event in &targets {
+            let ids_free = (event.prev_curr_id..event.curr_id).all(|id| self.get_record(id).is_none() && !self.get_data_root().join(id.to_string()).exists());
+            if self.curr_id != event.curr_id || !ids_free {
+                break;
+            }
+            self.curr_id = event.prev_curr_id;
+        }
+
+        self.pending_undoes = targets.iter().map(|e| e.seq).collect();
+        self.pending_moves = moved_back;
+        Ok(targets)
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+use std::error::Error;
+use std::collections::{HashMap, HashSet};
+use super::Database;
+use super::apply::Run;
+use super::entry::{data::Data, transform::Transform, connection::Connection, action::Action, metadata::MetaValue, new_uid};
+
+pub struct UpdateStep {
+    pub connection_id: u64, // The Apply connection to recompute
+    pub transform_id: u64,
+    pub in_data_ids: Vec<u64>,
+    pub out_data_id: u64, // The data this step makes a new version of
+    pub depends_on: Vec<u64> // The connection ids of the earlier steps whose outputs this step needs
+}
+
+impl fmt::Display for UpdateStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "apply transform {} to data {:?}, replacing data {} (connection {})", self.transform_id, self.in_data_ids, self.out_data_id, self.connection_id)
+    }
+}
+
+#[derive(Default)]
+pub struct UpdateReport {
+    pub new_transforms: Vec<(u64, u64)>, // (old transform id, new transform id) for transforms whose scripts have newer commits
+    pub updated: Vec<(u64, u64)>, // (old data id, new data id)
+    pub failed: Vec<(u64, String)>, // (connection id, error) for the steps whose scripts failed
+    pub skipped: Vec<u64> // The connection ids of the steps that were not run because a step they need failed
+}
+
+impl Database {
+    fn downstream_dfs(&self, id: u64, seen: &mut HashSet<u64>, post_order: &mut Vec<u64>) {
+        if !seen.insert(id) {
+            return;
+        }
+        // Newer versions are not downstream of the version they replace
+        for connection in self.out_connections(id).into_iter().filter(|c| c.action != Action::Update) {
+            for child in connection.out_ids() {
+                self.downstream_dfs(child, seen, post_order);
+            }
+        }
+        post_order.push(id);
+    }
+
+    /// The Apply connections to recompute when the data or transform with the given id changed, in the order they must run
+    pub fn update_plan(&self, id: u64) -> Result<Vec<UpdateStep>, Box<dyn Error>> {
+        if self.get_data(id).is_none() && self.get_transform(id).is_none() {
+            println!("Error: Data or transform with id {} does not exist", id);
+            return Err(format!("Data or transform with id {} does not exist", id).into());
+        }
+
+        // Everything downstream of id, in topological order
+        let mut post_order = vec![];
+        self.downstream_dfs(id, &mut HashSet::new(), &mut post_order);
+        let position: HashMap<u64, usize> = post_order.iter().rev().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        // The Apply connections that consume something downstream and whose output has not been replaced already
+        let mut steps: Vec<UpdateStep> = self.connection_vec
+            .iter()
+            .filter(|c| c.action == Action::Apply && c.in_transform_ids.len() == 1 && c.out_data_ids.len() == 1)
+            .filter(|c| c.in_ids().any(|in_id| position.contains_key(&in_id)) && self.newer_versions(c.out_data_ids[0]).is_empty())
+            .map(|c| UpdateStep {
+                connection_id: c.id,
+                transform_id: c.in_transform_ids[0],
+                in_data_ids: c.in_data_ids.clone(),
+                out_data_id: c.out_data_ids[0],
+                depends_on: vec![]
+            })
+            .collect();
+        steps.sort_by_key(|s| position[&s.out_data_id]);
+
+        // A step depends on the earlier steps that made any of its (possibly indirect) inputs
+        for i in 0..steps.len() {
+            let mut upstream = HashSet::new();
+            let mut stack: Vec<u64> = steps[i].in_data_ids.iter().copied().chain([steps[i].transform_id]).collect();
+            while let Some(up_id) = stack.pop() {
+                if position.contains_key(&up_id) && upstream.insert(up_id) {
+                    stack.extend(self.in_connections(up_id).iter().filter(|c| c.action != Action::Update).flat_map(|c| c.in_ids()));
+                }
+            }
+            steps[i].depends_on = steps[..i].iter().filter(|s| upstream.contains(&s.out_data_id)).map(|s| s.connection_id).collect();
+        }
+        Ok(steps)
+    }
+
+    fn connect_versions(&mut self, old_id: u64, new_id: u64, is_data: bool) -> Result<u64, Box<dyn Error>> {
+        let (in_data_ids, out_data_ids, in_transform_ids, out_transform_ids) = if is_data {
+            (vec![old_id], vec![new_id], vec![], vec![])
+        } else {
+            (vec![], vec![], vec![old_id], vec![new_id])
+        };
+        let connection = Connection {
+            id: self.curr_id,
+            uid: new_uid(),
+            md: HashMap::from([("time".to_owned(), MetaValue::now())]),
+            action: Action::Update,
+            in_data_ids,
+            out_data_ids,
+            in_transform_ids,
+            out_transform_ids,
+            in_data_hashes: vec![]
+        };
+        match self.find_connection(&connection) {
+            Some(id) => Ok(id),
+            None => self.try_add_connection(connection)
+        }
+    }
+
+    fn new_transform_version(&mut self, transform_id: u64) -> Result<Option<u64>, Box<dyn Error>> {
+        // Only transforms whose scripts have newer commits need a new version
+        let transform = self.get_transform(transform_id).unwrap();
+        let script_git_hashes = transform.head_hashes();
+        if script_git_hashes == transform.script_git_hashes {
+            return Ok(None);
+        }
+
+        let mut md = transform.md.clone();
+        md.insert("time".to_owned(), MetaValue::now());
+        let new_transform = Transform {
+            id: self.curr_id,
+            uid: new_uid(),
+            md,
+            script_paths: transform.script_paths.clone(),
+            script_args: transform.script_args.clone(),
+            script_git_hashes
+        };
+        let new_id = match self.find_transform(&new_transform) {
+            Some(id) => id,
+            None => self.try_add_transform(new_transform)?
+        };
+        self.connect_versions(transform_id, new_id, false)?;
+        Ok(Some(new_id))
+    }
+
+    fn finish_update_step(&mut self, step: &UpdateStep, run: Run, new_data: Data, reserved_id: bool) -> Result<u64, Box<dyn Error>> {
+        // The new version keeps the metadata of the old one
+        let old_md = self.get_data(step.out_data_id).unwrap().md.clone();
+        let (new_id, _) = self.finish_run(run, new_data, HashMap::new(), old_md, true, reserved_id)?;
+        if new_id == step.out_data_id {
+            // The scripts rewrote the same files, which finish_run already re-hashed
+            return Ok(new_id);
+        }
+        self.connect_versions(step.out_data_id, new_id, true)?;
+        Ok(new_id)
+    }
+
+    /// Recomputes everything downstream of the data or transform with the given id, running up to jobs independent
+    /// steps at once. A failing step only stops the steps that need its output.
+    pub fn update(&mut self, id: u64, jobs: usize) -> Result<UpdateReport, Box<dyn Error>> {
+        let steps = self.update_plan(id)?;
+        let mut report = UpdateReport::default();
+
+        // The data changed in place, so record its current contents
+        if let Some(data) = self.get_data_mut(id) {
+            data.hash()?;
+        }
+
+        // Transforms (downstream of id) whose scripts have newer commits get a new version
+        let mut versions = HashMap::new();
+        let mut transform_ids: Vec<u64> = steps.iter().map(|s| s.transform_id).chain([id]).collect();
+        transform_ids.sort_unstable();
+        transform_ids.dedup();
+        let mut downstream = HashSet::new();
+        self.downstream_dfs(id, &mut downstream, &mut vec![]);
+        for transform_id in transform_ids {
+            if self.get_transform(transform_id).is_some() && downstream.contains(&transform_id) {
+                if let Some(new_id) = self.new_transform_version(transform_id)? {
+                    versions.insert(transform_id, new_id);
+                    report.new_transforms.push((transform_id, new_id));
+                }
+            }
+        }
+
+        // Run the steps whose inputs are ready, jobs at a time
+        let mut succeeded: HashMap<u64, bool> = HashMap::new();
+        let mut remaining: Vec<&UpdateStep> = steps.iter().collect();
+        while !remaining.is_empty() {
+            let (ready, waiting): (Vec<&UpdateStep>, Vec<&UpdateStep>) = remaining.into_iter().partition(|s| s.depends_on.iter().all(|d| succeeded.contains_key(d)));
+            remaining = waiting;
+            if ready.is_empty() {
+                break; // Only possible with a cycle made with `connect`
+            }
+
+            // Skip the steps that need the output of a failed step
+            let mut runnable = vec![];
+            for step in ready {
+                if step.depends_on.iter().all(|d| succeeded[d]) {
+                    runnable.push(step);
+                } else {
+                    succeeded.insert(step.connection_id, false);
+                    report.skipped.push(step.connection_id);
+                }
+            }
+
+            for chunk in runnable.chunks(jobs.max(1)) {
+                // Runs in parallel each set their id aside when they start, a single run only takes its id if it adds data
+                let reserve_ids = chunk.len() > 1;
+                let first_id = self.curr_id;
+
+                // Set up the runs with the newest versions of their inputs
+                let mut runs = vec![];
+                for step in chunk {
+                    let transform_id = *versions.get(&step.transform_id).unwrap_or(&step.transform_id);
+                    let data_ids: Vec<u64> = step.in_data_ids.iter().map(|id| *versions.get(id).unwrap_or(id)).collect();
+                    let run = self.data_hashes(&data_ids).and_then(|hashes| self.start_run(transform_id, &data_ids, hashes, self.curr_id, false));
+                    match run {
+                        Ok(run) => {
+                            if reserve_ids {
+                                self.curr_id += 1;
+                            }
+                            runs.push((*step, run));
+                        }
+                        Err(e) => {
+                            succeeded.insert(step.connection_id, false);
+                            report.failed.push((step.connection_id, e.to_string()));
+                        }
+                    }
+                }
+
+                // Run the scripts in parallel, keeping what each printed so that the outputs do not interleave
+                let db = &*self;
+                let results: Vec<(Result<Data, String>, String)> = std::thread::scope(|scope| {
+                    let handles: Vec<_> = runs.iter().map(|(_, run)| scope.spawn(move || {
+                        let mut log = String::new();
+                        (db.run(run, &mut log).map_err(|e| e.to_string()), log)
+                    })).collect();
+                    handles.into_iter().map(|h| h.join().unwrap_or_else(|_| (Err("The run panicked".to_owned()), String::new()))).collect()
+                });
+
+                // Give back the ids of the runs at the end of the chunk that failed (the scripts saw the ids of the
+                // others, so they cannot move)
+                if reserve_ids {
+                    let last_id = runs.iter().zip(&results).filter(|(_, (result, _))| result.is_ok()).map(|((_, run), _)| run.id).max();
+                    self.curr_id = last_id.map_or(first_id, |id| id + 1);
+                }
+
+                // Register the new versions
+                for ((step, run), (result, log)) in runs.into_iter().zip(results) {
+                    println!("Output of the step to {}:", step);
+                    print!("{}", log);
+                    match result.and_then(|new_data| self.finish_update_step(step, run, new_data, reserve_ids).map_err(|e| e.to_string())) {
+                        Ok(new_id) => {
+                            succeeded.insert(step.connection_id, true);
+                            versions.insert(step.out_data_id, new_id);
+                            report.updated.push((step.out_data_id, new_id));
+                        }
+                        Err(e) => {
+                            succeeded.insert(step.connection_id, false);
+                            report.failed.push((step.connection_id, e));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+}
@@ -1,26 +1,19 @@
 use regex::Regex;
-use chrono::Utc;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
 
-pub fn is_permutation_small<T: PartialEq>(lhs: &[T], rhs: &[T]) -> bool {
+pub fn is_permutation<T: Ord>(lhs: &[T], rhs: &[T]) -> bool {
     if lhs.len() != rhs.len() {
         return false;
     }
 
-    // Horrible O(n^2) solution, but may be faster than using a HashMap for small arrays
-    // TODO: Measure performance and see if it's worth it to use a HashMap
-    for x in lhs {
-        let mut found = false;
-        for y in rhs {
-            if x == y {
-                found = true;
-                break;
-            }
-        }
-        if !found {
-            return false;
-        }
-    }
-    true
+    // Sorting is O(n log n), comparing every pair of elements would be O(n^2)
+    let mut lhs: Vec<&T> = lhs.iter().collect();
+    let mut rhs: Vec<&T> = rhs.iter().collect();
+    lhs.sort_unstable();
+    rhs.sort_unstable();
+    lhs == rhs
 }
 
 pub fn parse_kv_opt_string(s: Option<&str>, num_default_entries: Option<usize>) -> Vec<Option<String>> {
@@ -40,17 +33,38 @@ pub fn parse_kv_opt_string(s: Option<&str>, num_default_entries: Option<usize>)
         .collect()
 }
 
-pub fn parse_args(s: &str, id: u64) -> String {
+pub fn parse_args(s: &str, id: u64, out_dir: &Path) -> String {
     // TODO: This should probably be part of the Database struct so that we can easily grab
     // something from the metadata for an arg like {psidb::md::my_metadata_key} -> my_metadata_value
     let date_re = Regex::new(r"\{psidb::date\}").unwrap();
     let time_re = Regex::new(r"\{psidb::time\}").unwrap();
     let id_re = Regex::new(r"\{psidb::id\}").unwrap();
+    let out_dir_re = Regex::new(r"\{psidb::out_dir\}").unwrap();
 
     let s1 = date_re.replace_all(s, &Utc::now().format("%Y-%m-%d").to_string());
     let s2 = time_re.replace_all(&s1, &Utc::now().format("%H:%M:%S%:z").to_string());
     let s3 = id_re.replace_all(&s2, &id.to_string());
-    s3.into_owned()
+    let s4 = out_dir_re.replace_all(&s3, regex::NoExpand(out_dir.to_str().unwrap()));
+    s4.into_owned()
+}
+
+pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    // Recursively lists every file under dir (sorted so that the order is reproducible). Symlinks are listed, not
+    // followed, so that a link to a parent folder cannot make the listing endless.
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
 }
 
 pub fn verify_file_path<T>(path_str: T) -> Result<(), Box<dyn std::error::Error>>
@@ -100,4 +114,62 @@ pub fn safe_git_checkout_commit_restore(repo: &mut git2::Repository, did_stash:
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn hash_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut hasher = Sha256::new();
+    if path.is_dir() {
+        // A symlink to a folder (listed by `list_files`) hashes where it points
+        hasher.update(std::fs::read_link(path)?.as_os_str().as_encoded_bytes());
+    } else {
+        let mut file = std::fs::File::open(path)?;
+        std::io::copy(&mut file, &mut hasher)?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn hash_path(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    hash_path_with(path, &mut hash_file)
+}
+
+pub fn hash_path_with<F>(path: &Path, hash_file: &mut F) -> Result<String, Box<dyn std::error::Error>>
+where F: FnMut(&Path) -> Result<String, Box<dyn std::error::Error>> {
+    // Files hash their contents, directories hash the relative paths and hashes of every file they contain
+    if !path.is_dir() {
+        return hash_file(path);
+    }
+    let mut hasher = Sha256::new();
+    for file in list_files(path)? {
+        hasher.update(file.strip_prefix(path)?.to_str().unwrap().as_bytes());
+        hasher.update(hash_file(&file)?.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn hash_paths_with<T: AsRef<Path>, F>(paths: &[T], hash_file: &mut F) -> Result<String, Box<dyn std::error::Error>>
+where F: FnMut(&Path) -> Result<String, Box<dyn std::error::Error>> {
+    // A single hash for a whole set of paths (e.g. a dataset)
+    let mut hasher = Sha256::new();
+    for path in paths {
+        hasher.update(hash_path_with(path.as_ref(), hash_file)?.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>, Box<dyn std::error::Error>> {
+    // RFC 3339 (e.g. 2024-03-01T12:00:00Z), or a local date and time (e.g. 2024-03-01 or 2024-03-01 12:00)
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)));
+    match naive.and_then(|t| Local.from_local_datetime(&t).earliest()) {
+        Some(time) => Ok(time.with_timezone(&Utc)),
+        None => {
+            println!("Error: Could not parse the date {}, expected e.g. 2024-03-01, 2024-03-01 12:00 or 2024-03-01T12:00:00Z", s);
+            Err(format!("Could not parse the date {}", s).into())
+        }
+    }
+}
@@ -0,0 +1,49 @@
+// Each test binary uses only some of the helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use psidb_lib::database::Database;
+
+/// An empty folder of its own for the given test
+pub fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("psidb-test-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.canonicalize().unwrap()
+}
+
+/// A copy of the fixture database in its own folder, returning the path of its db.ron
+pub fn copy_fixture(name: &str, test: &str) -> PathBuf {
+    let dir = test_dir(test).join(".psidb");
+    fs::create_dir_all(&dir).unwrap();
+    let db_path = dir.join("db.ron");
+    fs::copy(fixture(name).join("db.ron"), &db_path).unwrap();
+    db_path
+}
+
+/// The folder of the fixture with the given name
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// A new empty database in a folder of its own, with the folder
+pub fn new_db(test: &str) -> (Database, PathBuf) {
+    let dir = test_dir(test);
+    let mut db = Database::new(dir.to_str()).unwrap();
+    db.write().unwrap();
+    (db, dir)
+}
+
+/// Writes a file with the given contents and returns its path
+pub fn write_file(path: &Path, contents: &str) -> String {
+    fs::write(path, contents).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+/// Writes an executable shell script and returns its path
+pub fn write_script(path: &Path, body: &str) -> String {
+    let path = write_file(path, &format!("#!/bin/sh\n{}\n", body));
+    fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    path
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+use psidb_lib::database::{Database, import::{ManifestColumns, ManifestKind}, entry::metadata::MetaValue};
+
+mod common;
+use common::{new_db, write_file};
+
+#[test]
+fn manifest_paths_are_not_typed() {
+    let (mut db, dir) = new_db("manifest");
+    write_file(&dir.join("007"), "a");
+    write_file(&dir.join("0.10"), "b");
+    write_file(&dir.join("1e5"), "c");
+    let manifest = write_file(&dir.join("manifest.csv"), "path,run,n\n007,01,5\n0.10,1,6\n1e5,1,7\n");
+
+    let columns = ManifestColumns { paths: "path", args: None, group_by: Some("run") };
+    let report = db.import_manifest(&manifest, ManifestKind::Data, &columns, None, false).unwrap();
+
+    // The runs 01 and 1 are different groups
+    assert_eq!(report.added.len(), 2);
+    for entry in &report.added {
+        for path in &entry.paths {
+            assert!(Path::new(path).is_file(), "{}", path);
+        }
+    }
+    let data = db.get_data(report.added[0].id.unwrap()).unwrap();
+    assert_eq!(data.paths, [dir.join("007").to_str().unwrap()]);
+    assert_eq!(data.md["run"], MetaValue::String("01".to_owned()));
+    // The other columns are typed
+    assert_eq!(data.md["n"], MetaValue::Int(5));
+    assert_eq!(db.get_data(report.added[1].id.unwrap()).unwrap().paths, [dir.join("0.10").to_str().unwrap(), dir.join("1e5").to_str().unwrap()]);
+}
+
+#[test]
+fn files_are_stored_once_every_row_is_added() {
+    let (mut db, dir) = new_db("manifest-store");
+    db.set_setting("store", Some("true")).unwrap();
+    let a = write_file(&dir.join("a.csv"), "a");
+    let columns = ManifestColumns { paths: "path", args: None, group_by: None };
+
+    // The second row fails, so the file of the first stays where it is
+    let manifest = write_file(&dir.join("broken.csv"), "path\na.csv\nmissing.csv\n");
+    assert!(db.import_manifest(&manifest, ManifestKind::Data, &columns, None, false).is_err());
+    assert!(!fs::symlink_metadata(&a).unwrap().file_type().is_symlink());
+
+    // Like the command line, start again from what is on disk
+    let mut db = Database::load_from(Path::new(&db.get_db_path())).unwrap();
+    db.set_setting("store", Some("true")).unwrap();
+    let manifest = write_file(&dir.join("manifest.csv"), "path\na.csv\n");
+    let report = db.import_manifest(&manifest, ManifestKind::Data, &columns, None, false).unwrap();
+    assert!(db.get_data(report.added[0].id.unwrap()).unwrap().stored);
+    assert!(fs::symlink_metadata(&a).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+}
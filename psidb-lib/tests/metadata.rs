@@ -0,0 +1,39 @@
+use psidb_lib::database::{Database, entry::metadata::{MetaValue, parser}};
+
+mod common;
+use common::copy_fixture;
+
+#[test]
+fn strings_keep_their_type() {
+    let db_path = copy_fixture("v0", "strings");
+    let mut db = Database::load_from(&db_path).unwrap();
+    db.set_md(0, Some("code:str=2024-03-01T00:00:00Z;when=2024-03-01T00:00:00Z"), &[]).unwrap();
+    db.write().unwrap();
+
+    let db = Database::load_from(&db_path).unwrap();
+    let md = &db.get_data(0).unwrap().md;
+    assert_eq!(md["code"], MetaValue::String("2024-03-01T00:00:00Z".to_owned()));
+    assert!(matches!(md["when"], MetaValue::DateTime(_)));
+    assert_eq!(db.query(&["code:str=2024-03-01T00:00:00Z"], None).unwrap(), vec![0]);
+
+    // The same goes for the log
+    let db = Database::at(db_path.parent().unwrap().parent().unwrap().to_str(), "2100-01-01").unwrap();
+    assert_eq!(db.get_data(0).unwrap().md["code"], MetaValue::String("2024-03-01T00:00:00Z".to_owned()));
+}
+
+#[test]
+fn entries_are_written_with_their_types() {
+    let db_path = copy_fixture("v0", "format");
+    let mut db = Database::load_from(&db_path).unwrap();
+    db.set_md(0, Some("code:str=5;n=5;x=2.0;ok:str=true;list:list=1,a;when=2024-03-01T00:00:00Z;'a:int'=1"), &[]).unwrap();
+
+    let md = &db.get_data(0).unwrap().md;
+    for (key, value) in md {
+        let entry = parser::format_entry(key, value).unwrap();
+        assert_eq!(parser::parse(&entry).unwrap()[key], *value, "{}", entry);
+    }
+    assert_eq!(parser::format_entry("code", &md["code"]).unwrap(), "code=\"5\"");
+    assert_eq!(parser::format_entry("a:int", &md["a:int"]).unwrap(), "a\\:int:int=1");
+    assert_eq!(parser::format_entry("n", &md["n"]).unwrap(), "n=5");
+    assert_eq!(parser::format_entry("when", &md["when"]).unwrap(), "when=2024-03-01T00:00:00Z");
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use psidb_lib::database::{Database, FORMAT_VERSION, entry::metadata::MetaValue};
+
+mod common;
+use common::copy_fixture;
+
+#[test]
+fn upgrades_a_v0_database() {
+    let db_path = copy_fixture("v0", "upgrade");
+    assert_eq!(Database::read_format_version(&db_path).unwrap(), 0);
+    assert_eq!(Database::pending_migrations(&db_path).unwrap().len(), FORMAT_VERSION as usize);
+
+    let db = Database::load_from(&db_path).unwrap();
+    // The upgrade is written where the database was loaded from, not where it was made
+    assert_eq!(db.get_db_path(), db_path.to_str().unwrap());
+    assert_eq!(Database::read_format_version(&db_path).unwrap(), FORMAT_VERSION);
+    let backup = Database::get_backup_dir(&db_path, 0).join("db.ron");
+    assert_eq!(fs::read_to_string(backup).unwrap(), fs::read_to_string(common::fixture("v0").join("db.ron")).unwrap());
+
+    // Metadata values are typed
+    let md = &db.get_data(0).unwrap().md;
+    assert_eq!(md["n"], MetaValue::Int(5));
+    assert_eq!(md["temperature"], MetaValue::Float(2.5));
+    assert_eq!(md["label"], MetaValue::String("raw".to_owned()));
+    assert!(matches!(md["time"], MetaValue::DateTime(_)));
+    assert!(fs::read_to_string(&db_path).unwrap().contains("\"$datetime\""));
+    // Strings that a typed value would write differently stay strings
+    for (key, value) in [("code", "007"), ("offset", "+5"), ("scale", "1e3")] {
+        assert_eq!(md[key], MetaValue::String(value.to_owned()));
+    }
+
+    // Argument strings are split into words
+    assert_eq!(db.get_transform(1).unwrap().script_args, vec![vec!["--drop".to_owned(), "bad rows".to_owned()]]);
+    assert_eq!(db.get_connection(3).unwrap().in_data_ids, vec![0]);
+
+    // The log starts with the entries the database had, then the upgrade
+    let events = db.read_log().unwrap();
+    let invocations: Vec<&str> = events.iter().map(|e| e.invocation.as_str()).collect();
+    assert_eq!(invocations, ["import", "migrate"]);
+}
+
+#[test]
+fn upgrades_only_once() {
+    let db_path = copy_fixture("v0", "once");
+    Database::load_from(&db_path).unwrap();
+    let log = fs::read_to_string(db_path.with_file_name("log")).unwrap();
+
+    let db = Database::load_from(&db_path).unwrap();
+    assert_eq!(fs::read_to_string(db_path.with_file_name("log")).unwrap(), log);
+    assert_eq!(db.get_data(0).unwrap().md["n"], MetaValue::Int(5));
+}
+
+#[test]
+fn refuses_newer_databases() {
+    let db_path = copy_fixture("v0", "newer");
+    let db_str = fs::read_to_string(&db_path).unwrap().replacen("Database(", &format!("Database(\n\tformat_version: {},", FORMAT_VERSION + 1), 1);
+    fs::write(&db_path, db_str).unwrap();
+    assert!(Database::load_from(&db_path).is_err());
+    assert!(!Database::get_backup_dir(&db_path, FORMAT_VERSION + 1).exists());
+}
+
+#[test]
+fn reads_without_upgrading() {
+    let db_path = copy_fixture("v0", "read");
+    let db_str = fs::read_to_string(&db_path).unwrap();
+
+    let mut db = Database::read_from(&db_path).unwrap();
+    assert_eq!(db.get_data(0).unwrap().md["n"], MetaValue::Int(5));
+    // Nothing is written, and the database cannot be
+    assert_eq!(fs::read_to_string(&db_path).unwrap(), db_str);
+    assert!(!db_path.with_file_name("log").exists());
+    assert!(!Database::get_backup_dir(&db_path, 0).exists());
+    assert!(db.write().is_err());
+}
+
+#[test]
+fn history_starts_at_the_import() {
+    let db_path = copy_fixture("v0", "history");
+    Database::load_from(&db_path).unwrap();
+    // The entries are from 2022, but the log only starts when the database is first loaded
+    let dir = db_path.parent().unwrap().parent().unwrap().to_str();
+    assert!(Database::at(dir, "2022-08-02").is_err());
+    assert_eq!(Database::at(dir, "2100-01-01").unwrap().get_data(0).unwrap().md["n"], MetaValue::Int(5));
+}
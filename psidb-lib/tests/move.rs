@@ -0,0 +1,64 @@
+use std::fs;
+
+mod common;
+use common::{new_db, write_file};
+
+#[test]
+fn moves_files_into_folders() {
+    let (mut db, dir) = new_db("move-into");
+    let a = write_file(&dir.join("a.csv"), "a");
+    db.add_data(&[a.as_str()], None).unwrap();
+    fs::create_dir(dir.join("out")).unwrap();
+
+    let report = db.move_path(&a, dir.join("out").to_str().unwrap(), false).unwrap();
+    assert_eq!(report.rewritten, [0]);
+    assert!(!dir.join("a.csv").exists());
+    assert_eq!(fs::read_to_string(dir.join("out/a.csv")).unwrap(), "a");
+    assert_eq!(db.get_data(0).unwrap().paths, [dir.join("out/a.csv").to_str().unwrap()]);
+    assert!(db.verify(None).unwrap().is_empty());
+}
+
+#[test]
+fn follows_moves_into_folders_made_by_other_tools() {
+    let (mut db, dir) = new_db("move-db-only");
+    let a = write_file(&dir.join("a.csv"), "a");
+    db.add_data(&[a.as_str()], None).unwrap();
+    fs::create_dir(dir.join("out")).unwrap();
+    fs::rename(&a, dir.join("out/a.csv")).unwrap();
+
+    // Like `mv a.csv out/` followed by `psidb mv --db-only a.csv out`
+    db.move_path(&a, dir.join("out").to_str().unwrap(), true).unwrap();
+    assert_eq!(db.get_data(0).unwrap().paths, [dir.join("out/a.csv").to_str().unwrap()]);
+    assert!(db.verify(None).unwrap().is_empty());
+
+    // A folder renamed to a new name is not moved into itself
+    fs::rename(dir.join("out"), dir.join("renamed")).unwrap();
+    db.move_path(dir.join("out").to_str().unwrap(), dir.join("renamed").to_str().unwrap(), true).unwrap();
+    assert_eq!(db.get_data(0).unwrap().paths, [dir.join("renamed/a.csv").to_str().unwrap()]);
+}
+
+#[test]
+fn moves_back_when_the_database_cannot_follow() {
+    let (mut db, dir) = new_db("move-rollback");
+    fs::create_dir(dir.join("src")).unwrap();
+    let a = write_file(&dir.join("src/a.csv"), "x");
+    db.add_data(&[a.as_str()], None).unwrap();
+    // a.csv was renamed to b.csv, which was added too
+    fs::rename(&a, dir.join("src/b.csv")).unwrap();
+    db.add_data(&[dir.join("src/b.csv").to_str().unwrap()], None).unwrap();
+
+    // Data 0 would be found at b.csv after the move, which is data 1
+    let src = dir.join("src");
+    assert!(db.move_path(src.to_str().unwrap(), dir.join("dst").to_str().unwrap(), false).is_err());
+    assert_eq!(fs::read_to_string(src.join("b.csv")).unwrap(), "x");
+    assert!(!dir.join("dst").exists());
+    assert_eq!(db.get_data(0).unwrap().paths, [a.as_str()]);
+    assert_eq!(db.get_data(1).unwrap().paths, [src.join("b.csv").to_str().unwrap()]);
+
+    // Missing sources and existing destinations are refused before anything moves
+    assert!(db.move_path(dir.join("none").to_str().unwrap(), dir.join("dst").to_str().unwrap(), false).is_err());
+    fs::create_dir(dir.join("dst")).unwrap();
+    write_file(&dir.join("dst/src"), "taken");
+    assert!(db.move_path(src.to_str().unwrap(), dir.join("dst").to_str().unwrap(), false).is_err());
+    assert!(src.join("b.csv").exists());
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use psidb_lib::database::{template::{Template, TemplateField}, entry::metadata::MetaValue};
+
+mod common;
+use common::{new_db, write_file};
+
+// A template with a single field
+fn template(name: &str, key: &str, default: Option<&str>) -> Template {
+    let field = TemplateField { key: key.to_owned(), type_name: None, required: true, allowed: vec![], regex: None, default: default.map(MetaValue::from) };
+    Template { name: name.to_owned(), fields: vec![field] }
+}
+
+// A pipeline that copies a.csv into the output directory of its step
+fn write_pipeline(dir: &Path) -> String {
+    let script = write_file(&dir.join("copy.sh"), "#!/bin/sh\ncp \"$1\" \"$PSIDB_OUT_DIR/\"\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    write_file(&dir.join("a.csv"), "a");
+    write_file(&dir.join("psidb.pipeline.ron"), r#"Pipeline(
+    transforms: { "copy": (scripts: [(path: "copy.sh")]) },
+    data: { "raw": Paths(["a.csv"]) },
+    steps: [(name: "copied", transform: "copy", inputs: ["raw"])],
+)"#)
+}
+
+#[test]
+fn pipeline_entries_follow_the_templates_and_the_store() {
+    let (mut db, dir) = new_db("pipeline-templates");
+    db.set_template(template("d", "project", Some("p1"))).unwrap();
+    db.set_template(template("t", "owner", Some("lab"))).unwrap();
+    db.set_setting("data_template", Some("d")).unwrap();
+    db.set_setting("transform_template", Some("t")).unwrap();
+    db.set_setting("store", Some("true")).unwrap();
+    let pipeline = write_pipeline(&dir);
+
+    let results = db.run_pipeline(Path::new(&pipeline)).unwrap();
+    let raw = db.get_data_vec().iter().find(|d| d.paths == [dir.join("a.csv").to_str().unwrap()]).unwrap();
+    assert_eq!(raw.md["project"], MetaValue::from("p1"));
+    assert!(raw.stored);
+    assert!(fs::symlink_metadata(dir.join("a.csv")).unwrap().file_type().is_symlink());
+    let transform_id = db.get_connection(results[0].connection_id).unwrap().in_transform_ids[0];
+    assert_eq!(db.get_transform(transform_id).unwrap().md["owner"], MetaValue::from("lab"));
+    assert_eq!(db.get_data(results[0].data_id).unwrap().md["project"], MetaValue::from("p1"));
+}
+
+#[test]
+fn pipeline_data_needs_the_required_fields() {
+    let (mut db, dir) = new_db("pipeline-required");
+    db.set_template(template("d", "project", None)).unwrap();
+    db.set_setting("data_template", Some("d")).unwrap();
+    let pipeline = write_pipeline(&dir);
+
+    assert!(db.run_pipeline(Path::new(&pipeline)).is_err());
+    assert!(db.get_data_vec().is_empty());
+}
@@ -0,0 +1,27 @@
+use std::fs;
+use std::path::Path;
+
+mod common;
+use common::{new_db, write_file, write_script};
+
+#[test]
+fn changed_inputs_make_outputs_stale() {
+    let (mut db, dir) = new_db("status");
+    let script = write_script(&dir.join("copy.sh"), "cp \"$1\" \"$PSIDB_OUT_DIR/\"");
+    let a = write_file(&dir.join("a.csv"), "a");
+    db.add_data(&[a.as_str()], None).unwrap();
+    db.add_transform(&[script], None, None, None).unwrap();
+    let (out_id, _, _) = db.apply(1, &[0], None, false, false).unwrap();
+
+    // The hashes of the inputs are kept, so that status and apply do not read unchanged files again
+    assert!(Path::new(&db.get_hash_cache_path()).exists());
+    assert!(db.status().is_empty());
+    assert!(db.apply(1, &[0], None, false, false).unwrap().2);
+
+    // Changing the input invalidates its cached hash
+    fs::write(&a, "changed").unwrap();
+    let mut stale: Vec<u64> = db.status().iter().map(|s| s.id).collect();
+    stale.sort_unstable();
+    assert_eq!(stale, [0, out_id]);
+    assert!(!db.apply(1, &[0], None, false, false).unwrap().2);
+}
@@ -0,0 +1,28 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+
+mod common;
+use common::{new_db, write_file, write_script};
+
+#[test]
+fn folders_with_symlink_loops_are_hashed() {
+    let (mut db, dir) = new_db("symlink-data");
+    fs::create_dir(dir.join("run")).unwrap();
+    write_file(&dir.join("run/a.csv"), "a");
+    symlink("..", dir.join("run/up")).unwrap();
+
+    let id = db.add_data(&[dir.join("run").to_str().unwrap()], None).unwrap();
+    assert!(db.verify(None).unwrap().is_empty());
+    assert_eq!(db.get_data(id).unwrap().trees[0].files.len(), 2);
+}
+
+#[test]
+fn outputs_with_symlink_loops_are_registered() {
+    let (mut db, dir) = new_db("symlink-apply");
+    let script = write_script(&dir.join("link.sh"), "echo b > \"$PSIDB_OUT_DIR/b.csv\"\nln -s . \"$PSIDB_OUT_DIR/self\"");
+    db.add_data(&[write_file(&dir.join("a.csv"), "a")], None).unwrap();
+    db.add_transform(&[script], None, None, None).unwrap();
+
+    let (out_id, _, _) = db.apply(1, &[0], None, false, false).unwrap();
+    assert_eq!(db.get_data(out_id).unwrap().paths.len(), 2);
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+use psidb_lib::database::{Database, entry::metadata::MetaValue};
+
+mod common;
+use common::{new_db, write_file};
+
+// The database as it is on disk
+fn reload(db: &Database) -> Database {
+    Database::load_from(Path::new(&db.get_db_path())).unwrap()
+}
+
+#[test]
+fn undoes_add_data_set_md_and_mv() {
+    let (mut db, dir) = new_db("undo");
+    let a = write_file(&dir.join("a.csv"), "a");
+    let b = dir.join("out/a.csv");
+    db.add_data(&[a.as_str()], None).unwrap();
+    db.write().unwrap();
+    db.set_md(0, Some("n=1"), &[]).unwrap();
+    db.write().unwrap();
+    fs::create_dir(dir.join("out")).unwrap();
+    db.move_path(&a, dir.join("out").to_str().unwrap(), false).unwrap();
+    db.write().unwrap();
+
+    // mv: the file goes back where it was
+    let mut db = reload(&db);
+    assert_eq!(db.undo(1).unwrap().len(), 1);
+    db.write().unwrap();
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+    assert!(!b.exists());
+    let mut db = reload(&db);
+    assert_eq!(db.get_data(0).unwrap().paths, [a.as_str()]);
+    assert!(db.verify(None).unwrap().is_empty());
+
+    // set-md
+    db.undo(1).unwrap();
+    db.write().unwrap();
+    let mut db = reload(&db);
+    assert!(!db.get_data(0).unwrap().md.contains_key("n"));
+
+    // add-data: the entry is removed and its id is given back, the file stays
+    db.undo(1).unwrap();
+    db.write().unwrap();
+    let mut db = reload(&db);
+    assert!(db.get_data_vec().is_empty());
+    assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+    assert_eq!(db.add_data(&[a.as_str()], None).unwrap(), 0);
+
+    // Nothing is left to undo
+    let mut db = reload(&db);
+    assert!(db.undo(1).is_err());
+}
+
+#[test]
+fn undo_does_nothing_when_an_operation_cannot_be_reverted() {
+    let (mut db, dir) = new_db("undo-blocked");
+    let a = write_file(&dir.join("a.csv"), "a");
+    db.add_data(&[a.as_str()], None).unwrap();
+    db.write().unwrap();
+    db.set_md(0, Some("n=1"), &[]).unwrap();
+    db.write().unwrap();
+    fs::create_dir(dir.join("out")).unwrap();
+    db.move_path(&a, dir.join("out").to_str().unwrap(), false).unwrap();
+    db.write().unwrap();
+    // Something took the old place of the moved file
+    write_file(&dir.join("a.csv"), "other");
+
+    let mut db = reload(&db);
+    assert!(db.undo(2).is_err());
+    assert_eq!(db.get_data(0).unwrap().paths, [dir.join("out/a.csv").to_str().unwrap()]);
+    assert_eq!(db.get_data(0).unwrap().md["n"], MetaValue::Int(1));
+    assert_eq!(fs::read_to_string(dir.join("out/a.csv")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(&a).unwrap(), "other");
+}
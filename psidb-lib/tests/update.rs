@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::Path;
+use psidb_lib::database::{Database, entry::action::Action};
+
+mod common;
+use common::{new_db, write_file, write_script};
+
+// data 0 -> transform 1 -> data 2 (connection 3)
+// data 2 -> transform 1 -> data 4 (connection 6)
+// data 0 and data 4 -> transform 1 -> data 5 (connection 7)
+fn pipeline(test: &str) -> Database {
+    let (mut db, dir) = new_db(test);
+    let script = write_file(&dir.join("copy.sh"), "#!/bin/sh\n");
+    assert_eq!(db.add_data(&[write_file(&dir.join("a.csv"), "a")], None).unwrap(), 0);
+    assert_eq!(db.add_transform(&[script], None, None, None).unwrap(), 1);
+    assert_eq!(db.add_data(&[write_file(&dir.join("b.csv"), "b")], None).unwrap(), 2);
+    assert_eq!(db.connect(Action::Apply, Some(&[0]), Some(&[2]), Some(&[1]), None, None).unwrap(), 3);
+    assert_eq!(db.add_data(&[write_file(&dir.join("c.csv"), "c")], None).unwrap(), 4);
+    assert_eq!(db.add_data(&[write_file(&dir.join("d.csv"), "d")], None).unwrap(), 5);
+    assert_eq!(db.connect(Action::Apply, Some(&[2]), Some(&[4]), Some(&[1]), None, None).unwrap(), 6);
+    assert_eq!(db.connect(Action::Apply, Some(&[0, 4]), Some(&[5]), Some(&[1]), None, None).unwrap(), 7);
+    db
+}
+
+// The connection ids of the steps, with the connection ids they depend on
+fn plan(db: &Database, id: u64) -> Vec<(u64, Vec<u64>)> {
+    db.update_plan(id).unwrap().into_iter().map(|s| (s.connection_id, s.depends_on)).collect()
+}
+
+#[test]
+fn steps_run_after_the_steps_they_need() {
+    let db = pipeline("order");
+    assert_eq!(plan(&db, 0), [(3, vec![]), (6, vec![3]), (7, vec![3, 6])]);
+    // A change of the transform reruns everything it made
+    assert_eq!(plan(&db, 1), [(3, vec![]), (6, vec![3]), (7, vec![3, 6])]);
+    // Only what is downstream is rerun
+    assert_eq!(plan(&db, 2), [(6, vec![]), (7, vec![6])]);
+    assert_eq!(plan(&db, 4), [(7, vec![])]);
+    assert!(plan(&db, 5).is_empty());
+    assert!(db.update_plan(100).is_err());
+}
+
+#[test]
+fn replaced_outputs_are_not_recomputed() {
+    let mut db = pipeline("replaced");
+    let dir = common::test_dir("replaced-new");
+    let new_id = db.add_data(&[write_file(&dir.join("c2.csv"), "c2")], None).unwrap();
+    db.connect(Action::Update, Some(&[4]), Some(&[new_id]), None, None, None).unwrap();
+
+    // Data 4 has a newer version, so connection 6 is not rerun, and the newer version is not downstream of data 4
+    assert_eq!(plan(&db, 0), [(3, vec![]), (7, vec![3])]);
+    assert_eq!(plan(&db, 4), [(7, vec![])]);
+}
+
+// Every id from 0 up is used
+fn check_ids(db: &Database) {
+    let mut ids: Vec<u64> = db.get_data_vec().iter().map(|d| d.id)
+        .chain(db.get_transform_vec().iter().map(|t| t.id))
+        .chain(db.get_connection_vec().iter().map(|c| c.id))
+        .collect();
+    ids.sort_unstable();
+    assert_eq!(ids, (0..ids.len() as u64).collect::<Vec<u64>>());
+}
+
+fn contents(db: &Database, id: u64) -> String {
+    fs::read_to_string(&db.get_data(id).unwrap().paths[0]).unwrap()
+}
+
+// data 0 -> copy (transform 1) -> data 2 (connection 3) -> copy -> data 4 (connection 5)
+// data 0 -> upper (transform 6) -> data 7 (connection 8)
+// copy fails on inputs that contain "fail"
+fn scripted_pipeline(test: &str) -> (Database, String) {
+    let (mut db, dir) = new_db(test);
+    let copy = write_script(&dir.join("copy.sh"), "if grep -q fail \"$1\"; then exit 1; fi\ncp \"$1\" \"$PSIDB_OUT_DIR/\"");
+    let upper = write_script(&dir.join("upper.sh"), "tr a-z A-Z < \"$1\" > \"$PSIDB_OUT_DIR/upper.csv\"");
+    let a = write_file(&dir.join("a.csv"), "a");
+    db.add_data(&[a.as_str()], None).unwrap();
+    db.add_transform(&[copy], None, None, None).unwrap();
+    assert_eq!(db.apply(1, &[0], None, false, false).unwrap().0, 2);
+    assert_eq!(db.apply(1, &[2], None, false, false).unwrap().0, 4);
+    db.add_transform(&[upper], None, None, None).unwrap();
+    assert_eq!(db.apply(6, &[0], None, false, false).unwrap().0, 7);
+    (db, a)
+}
+
+#[test]
+fn updates_run_the_scripts() {
+    let (mut db, a) = scripted_pipeline("run");
+    fs::write(&a, "b").unwrap();
+
+    // Connections 3 and 8 run at the same time, then connection 5
+    let report = db.update(0, 2).unwrap();
+    assert!(report.failed.is_empty() && report.skipped.is_empty());
+    let mut updated: Vec<u64> = report.updated.iter().map(|(old, _)| *old).collect();
+    assert_eq!(updated.pop(), Some(4));
+    updated.sort_unstable();
+    assert_eq!(updated, [2, 7]);
+    for (old, new) in &report.updated {
+        assert_eq!(db.newer_versions(*old), [*new]);
+        assert!(Path::new(&db.get_data(*new).unwrap().paths[0]).starts_with(db.get_data_root()));
+    }
+    assert_eq!(contents(&db, db.newer_versions(2)[0]), "b");
+    assert_eq!(contents(&db, db.newer_versions(4)[0]), "b");
+    assert_eq!(contents(&db, db.newer_versions(7)[0]), "B");
+    assert!(db.status().is_empty());
+    check_ids(&db);
+}
+
+#[test]
+fn failed_steps_skip_the_steps_that_need_them() {
+    for jobs in [1, 2] {
+        let (mut db, a) = scripted_pipeline(&format!("failed-{}", jobs));
+        fs::write(&a, "fail").unwrap();
+
+        let report = db.update(0, jobs).unwrap();
+        let failed: Vec<u64> = report.failed.iter().map(|(id, _)| *id).collect();
+        assert_eq!(failed, [3]);
+        assert_eq!(report.skipped, [5]);
+        assert_eq!(report.updated.len(), 1);
+        assert_eq!(contents(&db, db.newer_versions(7)[0]), "FAIL");
+        assert!(db.newer_versions(2).is_empty() && db.newer_versions(4).is_empty());
+        // The failed run did not use up an id (with 2 jobs, it was the last run of its chunk)
+        check_ids(&db);
+    }
+}